@@ -0,0 +1,288 @@
+use std::fmt;
+use std::net::IpAddr;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+
+pub const DEFAULT_PORT: u16 = 25565;
+
+/// A server address as players type it, e.g. `play.example.net` or `127.0.0.1:25566`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAddress {
+    pub host: String,
+    /// `None` to look up the `_minecraft._tcp` SRV record, then fall back to 25565.
+    pub port: Option<u16>,
+}
+
+/// Where the bot actually connects once the address is resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+impl ServerAddress {
+    /// Accepts `host`, `host:port`, IPv6 literals and bracketed IPv6 literals with a port.
+    pub fn parse(address: &str) -> Result<Self> {
+        let address = address.trim();
+
+        let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+            let Some((host, rest)) = rest.split_once(']') else {
+                bail!("Missing closing bracket in {address}");
+            };
+
+            match rest {
+                "" => (host, None),
+                rest => match rest.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => bail!("Unexpected {rest} after the host in {address}"),
+                },
+            }
+        } else {
+            match address.split_once(':') {
+                // More than one colon is an IPv6 literal without a port
+                Some((_, port)) if port.contains(':') => (address, None),
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            }
+        };
+
+        if host.is_empty() {
+            bail!("Missing host in {address}");
+        }
+
+        let port = match port {
+            Some(port) => match port.parse() {
+                Ok(port) => Some(port),
+                Err(_) => bail!("Invalid port {port} in {address}"),
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// Uses the SRV record when no port was given, like the vanilla client. Lookup failures
+    /// fall back to the default port since most servers have no SRV record.
+    pub async fn resolve(&self, resolver: &dyn SrvResolver) -> Endpoint {
+        if let Some(port) = self.port {
+            return Endpoint::new(&self.host, port);
+        }
+
+        if self.host.parse::<IpAddr>().is_err() {
+            match resolver.lookup_srv(&self.host).await {
+                Ok(Some(endpoint)) => return endpoint,
+                Ok(None) => {}
+                Err(e) => eprintln!("Error resolving SRV record of {}: {:?}", self.host, e),
+            }
+        }
+
+        Endpoint::new(&self.host, DEFAULT_PORT)
+    }
+}
+
+impl Endpoint {
+    pub fn new(host: impl ToString, port: u16) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(_)) => write!(f, "[{}]:{}", self.host, self.port),
+            _ => write!(f, "{}:{}", self.host, self.port),
+        }
+    }
+}
+
+/// Finds the endpoint a `_minecraft._tcp` SRV record points to, can be replaced to avoid
+/// real DNS queries.
+#[async_trait]
+pub trait SrvResolver: Send + Sync {
+    /// `Ok(None)` when the host has no SRV record.
+    async fn lookup_srv(&self, host: &str) -> Result<Option<Endpoint>>;
+}
+
+pub struct DnsResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl Default for DnsResolver {
+    /// Uses the system's DNS configuration, or public resolvers if it can't be read.
+    fn default() -> Self {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|_| {
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+
+        Self { resolver }
+    }
+}
+
+#[async_trait]
+impl SrvResolver for DnsResolver {
+    async fn lookup_srv(&self, host: &str) -> Result<Option<Endpoint>> {
+        let lookup = match self
+            .resolver
+            .srv_lookup(format!("_minecraft._tcp.{}.", host.trim_end_matches('.')))
+            .await
+        {
+            Ok(lookup) => lookup,
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let Some(record) = lookup.iter().min_by_key(|record| record.priority()) else {
+            return Ok(None);
+        };
+
+        let target = record.target().to_utf8();
+        let target = target.trim_end_matches('.');
+
+        Ok(Some(Endpoint::new(target, record.port())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    fn address(host: &str, port: Option<u16>) -> ServerAddress {
+        ServerAddress {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("play.example.net", address("play.example.net", None)),
+            (
+                "play.example.net:25566",
+                address("play.example.net", Some(25566)),
+            ),
+            (" 127.0.0.1:25565 ", address("127.0.0.1", Some(25565))),
+            ("::1", address("::1", None)),
+            ("2001:db8::1", address("2001:db8::1", None)),
+            ("[::1]", address("::1", None)),
+            ("[2001:db8::1]:25566", address("2001:db8::1", Some(25566))),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(ServerAddress::parse(input).unwrap(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for input in [
+            "",
+            ":25565",
+            "host:",
+            "host:port",
+            "host:65536",
+            "[::1",
+            "[::1]x",
+            "[]:1",
+        ] {
+            assert!(ServerAddress::parse(input).is_err(), "{input}");
+        }
+    }
+
+    /// Answers every lookup with the same result and counts them.
+    struct MockResolver {
+        result: fn() -> Result<Option<Endpoint>>,
+        lookups: AtomicUsize,
+    }
+
+    impl MockResolver {
+        fn new(result: fn() -> Result<Option<Endpoint>>) -> Self {
+            Self {
+                result,
+                lookups: AtomicUsize::new(0),
+            }
+        }
+
+        fn lookups(&self) -> usize {
+            self.lookups.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl SrvResolver for MockResolver {
+        async fn lookup_srv(&self, _host: &str) -> Result<Option<Endpoint>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            (self.result)()
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_uses_the_srv_record() {
+        let resolver = MockResolver::new(|| Ok(Some(Endpoint::new("mc1.example.net", 25570))));
+        let endpoint = address("example.net", None).resolve(&resolver).await;
+
+        assert_eq!(endpoint, Endpoint::new("mc1.example.net", 25570));
+        assert_eq!(resolver.lookups(), 1);
+    }
+
+    #[tokio::test]
+    async fn resolve_falls_back_to_the_default_port() {
+        let missing = MockResolver::new(|| Ok(None));
+        let failing = MockResolver::new(|| Err(anyhow!("SERVFAIL")));
+
+        for resolver in [missing, failing] {
+            let endpoint = address("example.net", None).resolve(&resolver).await;
+
+            assert_eq!(endpoint, Endpoint::new("example.net", DEFAULT_PORT));
+            assert_eq!(resolver.lookups(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_skips_the_lookup() {
+        let resolver = MockResolver::new(|| Ok(Some(Endpoint::new("mc1.example.net", 25570))));
+
+        let cases = [
+            (
+                address("example.net", Some(25566)),
+                Endpoint::new("example.net", 25566),
+            ),
+            (
+                address("127.0.0.1", None),
+                Endpoint::new("127.0.0.1", DEFAULT_PORT),
+            ),
+            (address("::1", None), Endpoint::new("::1", DEFAULT_PORT)),
+        ];
+
+        for (address, expected) in cases {
+            assert_eq!(address.resolve(&resolver).await, expected);
+        }
+
+        assert_eq!(resolver.lookups(), 0);
+    }
+
+    #[test]
+    fn endpoint_display() {
+        assert_eq!(
+            Endpoint::new("example.net", 25565).to_string(),
+            "example.net:25565"
+        );
+        assert_eq!(Endpoint::new("::1", 25565).to_string(), "[::1]:25565");
+    }
+}
@@ -0,0 +1,28 @@
+mod cache;
+mod microsoft;
+mod offline;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+pub use cache::TokenCache;
+pub use microsoft::{DeviceCode, MicrosoftAuthenticator, MicrosoftEndpoints};
+pub use offline::Offline;
+
+use crate::session::Session;
+
+/// Provides the profile a bot logs in with.
+///
+/// Offline accounts have an empty access token and can only join offline-mode servers.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    async fn authenticate(&self) -> Result<Session>;
+}
+
+/// An already authenticated session, e.g. one obtained outside of the bot.
+#[async_trait]
+impl Authenticator for Session {
+    async fn authenticate(&self) -> Result<Session> {
+        Ok(self.clone())
+    }
+}
@@ -0,0 +1,231 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use super::microsoft::EXPIRY_MARGIN;
+use super::{Authenticator, MicrosoftAuthenticator};
+use crate::session::Session;
+
+#[derive(Serialize, Deserialize)]
+struct CachedTokens {
+    refresh_token: String,
+    username: String,
+    uuid: String,
+    access_token: String,
+    expires_at: u64,
+}
+
+/// Keeps the tokens of a Microsoft login in a JSON file so the device code only has to be
+/// entered once. Expired sessions are renewed with the refresh token.
+pub struct TokenCache {
+    path: PathBuf,
+    inner: MicrosoftAuthenticator,
+    // Bots sharing the cache must not refresh the same token concurrently
+    lock: Mutex<()>,
+}
+
+impl TokenCache {
+    pub fn new(path: impl Into<PathBuf>, inner: MicrosoftAuthenticator) -> Self {
+        Self {
+            path: path.into(),
+            inner,
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn read(&self) -> Option<CachedTokens> {
+        let data = tokio::fs::read(&self.path).await.ok()?;
+
+        match serde_json::from_slice(&data) {
+            Ok(tokens) => Some(tokens),
+            Err(e) => {
+                eprintln!("[WARN] Ignoring invalid token cache: {:?}", e);
+                None
+            }
+        }
+    }
+
+    async fn write(&self, tokens: &CachedTokens) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Write then rename like the world cache, only the owner may read the tokens
+        let tmp = self.path.with_extension("tmp");
+        let _ = tokio::fs::remove_file(&tmp).await;
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(&tmp).await?;
+        file.write_all(&serde_json::to_vec_pretty(tokens)?).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        tokio::fs::rename(tmp, &self.path).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Authenticator for TokenCache {
+    async fn authenticate(&self) -> Result<Session> {
+        let _guard = self.lock.lock().await;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let cached = self.read().await;
+
+        if let Some(ref tokens) = cached {
+            if tokens.expires_at > now + EXPIRY_MARGIN {
+                return Ok(Session {
+                    username: tokens.username.clone(),
+                    uuid: tokens.uuid.clone(),
+                    access_token: tokens.access_token.clone(),
+                });
+            }
+        }
+
+        let refreshed = match cached {
+            Some(tokens) => match self.inner.refresh(&tokens.refresh_token).await {
+                Ok(token) => Some(token),
+                Err(e) => {
+                    eprintln!("[WARN] {:?}, logging in again...", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let token = match refreshed {
+            Some(token) => token,
+            None => self.inner.device_code_login().await?,
+        };
+
+        let (session, expires_at) = self.inner.minecraft_session(&token.access_token).await?;
+
+        let tokens = CachedTokens {
+            refresh_token: token.refresh_token,
+            username: session.username.clone(),
+            uuid: session.uuid.clone(),
+            access_token: session.access_token.clone(),
+            expires_at,
+        };
+
+        if let Err(e) = self.write(&tokens).await {
+            eprintln!("[ERROR] Failed to write token cache: {:?}", e);
+        }
+
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::auth::microsoft::tests::{authenticator, services};
+
+    fn cache_path(dir: &TempDir) -> PathBuf {
+        dir.path().join("auth").join("tokens.json")
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_the_login_across_restarts() {
+        let server = services(86400).await;
+        let dir = TempDir::new().unwrap();
+
+        let session = TokenCache::new(cache_path(&dir), authenticator(&server))
+            .authenticate()
+            .await
+            .unwrap();
+        assert_eq!(session.username, "Notch");
+
+        let data = std::fs::read(cache_path(&dir)).unwrap();
+        let tokens: CachedTokens = serde_json::from_slice(&data).unwrap();
+        assert_eq!(tokens.refresh_token, "refresh");
+        assert_eq!(tokens.access_token, "minecraft");
+        assert!(!cache_path(&dir).with_extension("tmp").exists());
+
+        // A new authenticator, as after a restart, reads the session back
+        let session = TokenCache::new(cache_path(&dir), authenticator(&server))
+            .authenticate()
+            .await
+            .unwrap();
+
+        assert_eq!(session.uuid, "069a79f444e94726a5befca90e38aaf5");
+        assert_eq!(server.requests_to("/devicecode").len(), 1);
+        assert_eq!(server.requests_to("/minecraft/profile").len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(start_paused = true)]
+    async fn only_the_owner_can_read_the_tokens() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let server = services(86400).await;
+        let dir = TempDir::new().unwrap();
+
+        TokenCache::new(cache_path(&dir), authenticator(&server))
+            .authenticate()
+            .await
+            .unwrap();
+
+        let mode = std::fs::metadata(cache_path(&dir))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refreshes_expired_tokens() {
+        let server = services(0).await;
+        let dir = TempDir::new().unwrap();
+
+        TokenCache::new(cache_path(&dir), authenticator(&server))
+            .authenticate()
+            .await
+            .unwrap();
+
+        let session = TokenCache::new(cache_path(&dir), authenticator(&server))
+            .authenticate()
+            .await
+            .unwrap();
+
+        assert_eq!(session.username, "Notch");
+        assert_eq!(server.requests_to("/devicecode").len(), 1);
+        assert_eq!(server.requests_to("/minecraft/profile").len(), 2);
+
+        let refresh = server.requests_to("/token").pop().unwrap();
+        assert!(refresh.body.contains("grant_type=refresh_token"));
+        assert!(refresh.body.contains("refresh_token=refresh"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ignores_a_broken_cache() {
+        let server = services(86400).await;
+        let dir = TempDir::new().unwrap();
+
+        std::fs::create_dir_all(cache_path(&dir).parent().unwrap()).unwrap();
+        std::fs::write(cache_path(&dir), b"{ not json").unwrap();
+
+        let session = TokenCache::new(cache_path(&dir), authenticator(&server))
+            .authenticate()
+            .await
+            .unwrap();
+
+        assert_eq!(session.access_token, "minecraft");
+        assert_eq!(server.requests_to("/devicecode").len(), 1);
+
+        let data = std::fs::read(cache_path(&dir)).unwrap();
+        assert!(serde_json::from_slice::<CachedTokens>(&data).is_ok());
+    }
+}
@@ -0,0 +1,472 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use super::Authenticator;
+use crate::session::Session;
+
+/// Refresh a bit before the real expiry so a session never expires mid-login.
+pub(super) const EXPIRY_MARGIN: u64 = 5 * 60;
+const SCOPE: &str = "XboxLive.signin offline_access";
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Base URLs of every service involved in a Microsoft login, overridable to test against
+/// local stand-ins.
+#[derive(Debug, Clone)]
+pub struct MicrosoftEndpoints {
+    pub oauth: String,
+    pub xbox_user: String,
+    pub xsts: String,
+    pub minecraft: String,
+}
+
+impl Default for MicrosoftEndpoints {
+    fn default() -> Self {
+        Self {
+            oauth: "https://login.microsoftonline.com/consumers/oauth2/v2.0".to_string(),
+            xbox_user: "https://user.auth.xboxlive.com".to_string(),
+            xsts: "https://xsts.auth.xboxlive.com".to_string(),
+            minecraft: "https://api.minecraftservices.com".to_string(),
+        }
+    }
+}
+
+/// What the user has to enter on the verification page to approve the login.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceCode {
+    pub user_code: String,
+    pub verification_uri: String,
+    pub message: String,
+    device_code: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TokenResponse {
+    Token(MicrosoftToken),
+    Error { error: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct MicrosoftToken {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct XboxResponse {
+    token: String,
+    display_claims: DisplayClaims,
+}
+
+#[derive(Deserialize)]
+struct DisplayClaims {
+    xui: Vec<UserHash>,
+}
+
+#[derive(Deserialize)]
+struct UserHash {
+    uhs: String,
+}
+
+#[derive(Deserialize)]
+struct MinecraftToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct Profile {
+    id: String,
+    name: String,
+}
+
+/// The last login, kept so reconnects don't ask for a new device code.
+struct CachedLogin {
+    token: MicrosoftToken,
+    session: Session,
+    expires_at: u64,
+}
+
+/// Logs in with a Microsoft account using the device code flow, then exchanges the token
+/// through Xbox Live for a Minecraft session.
+///
+/// The session is kept in memory and renewed with the refresh token once it expires, use
+/// `TokenCache` to also keep it across restarts.
+pub struct MicrosoftAuthenticator {
+    client_id: String,
+    endpoints: MicrosoftEndpoints,
+    client: reqwest::Client,
+    on_device_code: Box<dyn Fn(&DeviceCode) + Send + Sync>,
+    // Also serializes logins, so bots sharing the authenticator don't each ask for a code
+    cached: Mutex<Option<CachedLogin>>,
+}
+
+impl MicrosoftAuthenticator {
+    /// `client_id` is the id of an Azure app registered for personal Microsoft accounts and
+    /// allowed to use Xbox Live, with public client flows enabled.
+    pub fn new(client_id: impl ToString) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            endpoints: MicrosoftEndpoints::default(),
+            client: reqwest::Client::new(),
+            on_device_code: Box::new(|code| println!("{}", code.message)),
+            cached: Mutex::new(None),
+        }
+    }
+
+    pub fn with_endpoints(mut self, endpoints: MicrosoftEndpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Called with the code the user has to enter, prints the instructions by default.
+    pub fn on_device_code(mut self, f: impl Fn(&DeviceCode) + Send + Sync + 'static) -> Self {
+        self.on_device_code = Box::new(f);
+        self
+    }
+
+    pub(crate) async fn device_code_login(&self) -> Result<MicrosoftToken> {
+        let code: DeviceCode = self
+            .client
+            .post(format!("{}/devicecode", self.endpoints.oauth))
+            .form(&[("client_id", self.client_id.as_str()), ("scope", SCOPE)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        (self.on_device_code)(&code);
+
+        let deadline = SystemTime::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval.max(1));
+
+        while SystemTime::now() < deadline {
+            tokio::time::sleep(interval).await;
+
+            let response: TokenResponse = self
+                .client
+                .post(format!("{}/token", self.endpoints.oauth))
+                .form(&[
+                    ("client_id", self.client_id.as_str()),
+                    ("grant_type", DEVICE_CODE_GRANT),
+                    ("device_code", code.device_code.as_str()),
+                ])
+                .send()
+                .await?
+                .json()
+                .await?;
+
+            match response {
+                TokenResponse::Token(token) => return Ok(token),
+                TokenResponse::Error { error } if error == "authorization_pending" => {}
+                TokenResponse::Error { error } if error == "slow_down" => {
+                    interval += Duration::from_secs(5);
+                }
+                TokenResponse::Error { error } => bail!("Microsoft login failed: {error}"),
+            }
+        }
+
+        bail!("Microsoft login timed out, the device code expired")
+    }
+
+    pub(crate) async fn refresh(&self, refresh_token: &str) -> Result<MicrosoftToken> {
+        let response: TokenResponse = self
+            .client
+            .post(format!("{}/token", self.endpoints.oauth))
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+                ("scope", SCOPE),
+            ])
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        match response {
+            TokenResponse::Token(token) => Ok(token),
+            TokenResponse::Error { error } => bail!("Failed to refresh Microsoft token: {error}"),
+        }
+    }
+
+    /// Exchanges a Microsoft access token for a Minecraft session and its expiry, in seconds
+    /// since the Unix epoch.
+    pub(crate) async fn minecraft_session(&self, access_token: &str) -> Result<(Session, u64)> {
+        let xbox: XboxResponse = self
+            .client
+            .post(format!("{}/user/authenticate", self.endpoints.xbox_user))
+            .json(&json!({
+                "Properties": {
+                    "AuthMethod": "RPS",
+                    "SiteName": "user.auth.xboxlive.com",
+                    "RpsTicket": format!("d={access_token}"),
+                },
+                "RelyingParty": "http://auth.xboxlive.com",
+                "TokenType": "JWT",
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let xsts: XboxResponse = self
+            .client
+            .post(format!("{}/xsts/authorize", self.endpoints.xsts))
+            .json(&json!({
+                "Properties": {
+                    "SandboxId": "RETAIL",
+                    "UserTokens": [xbox.token],
+                },
+                "RelyingParty": "rp://api.minecraftservices.com/",
+                "TokenType": "JWT",
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let Some(user) = xsts.display_claims.xui.first() else {
+            bail!("Xbox Live didn't return a user hash");
+        };
+
+        let minecraft: MinecraftToken = self
+            .client
+            .post(format!(
+                "{}/authentication/login_with_xbox",
+                self.endpoints.minecraft
+            ))
+            .json(&json!({
+                "identityToken": format!("XBL3.0 x={};{}", user.uhs, xsts.token),
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // Fails with a 404 when the account doesn't own the game
+        let profile: Profile = self
+            .client
+            .get(format!("{}/minecraft/profile", self.endpoints.minecraft))
+            .bearer_auth(&minecraft.access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let expires_at =
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + minecraft.expires_in;
+
+        let session = Session {
+            username: profile.name,
+            uuid: profile.id,
+            access_token: minecraft.access_token,
+        };
+
+        Ok((session, expires_at))
+    }
+}
+
+#[async_trait]
+impl Authenticator for MicrosoftAuthenticator {
+    async fn authenticate(&self) -> Result<Session> {
+        let mut cached = self.cached.lock().await;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        if let Some(ref login) = *cached {
+            if login.expires_at > now + EXPIRY_MARGIN {
+                return Ok(login.session.clone());
+            }
+        }
+
+        let refreshed = match cached.take() {
+            Some(login) => match self.refresh(&login.token.refresh_token).await {
+                Ok(token) => Some(token),
+                Err(e) => {
+                    eprintln!("[WARN] {:?}, logging in again...", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let token = match refreshed {
+            Some(token) => token,
+            None => self.device_code_login().await?,
+        };
+
+        let (session, expires_at) = self.minecraft_session(&token.access_token).await?;
+
+        *cached = Some(CachedLogin {
+            token,
+            session: session.clone(),
+            expires_at,
+        });
+
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_server::TestServer;
+
+    // Time starts paused in these tests so the device code polls don't wait for real seconds
+
+    /// Stands in for every service of the login, `expires_in` is the Minecraft token's.
+    pub(crate) async fn services(expires_in: u64) -> TestServer {
+        let polls = AtomicUsize::new(0);
+
+        TestServer::start(move |request| {
+            let body = match request.path.as_str() {
+                "/devicecode" => json!({
+                    "user_code": "ABCD-1234",
+                    "verification_uri": "https://microsoft.com/link",
+                    "message": "Enter ABCD-1234",
+                    "device_code": "device",
+                    "expires_in": 60,
+                    "interval": 0,
+                }),
+                // The user approves the login on the second poll
+                "/token"
+                    if request.body.contains("device_code")
+                        && polls.fetch_add(1, Ordering::SeqCst) == 0 =>
+                {
+                    json!({ "error": "authorization_pending" })
+                }
+                "/token" => json!({
+                    "access_token": "microsoft",
+                    "refresh_token": "refresh",
+                }),
+                "/user/authenticate" => json!({
+                    "Token": "xbox",
+                    "DisplayClaims": { "xui": [{ "uhs": "hash" }] },
+                }),
+                "/xsts/authorize" => json!({
+                    "Token": "xsts",
+                    "DisplayClaims": { "xui": [{ "uhs": "hash" }] },
+                }),
+                "/authentication/login_with_xbox" => json!({
+                    "access_token": "minecraft",
+                    "expires_in": expires_in,
+                }),
+                "/minecraft/profile" => json!({
+                    "id": "069a79f444e94726a5befca90e38aaf5",
+                    "name": "Notch",
+                }),
+                _ => return (404, "{}".to_string()),
+            };
+
+            (200, body.to_string())
+        })
+        .await
+    }
+
+    pub(crate) fn authenticator(server: &TestServer) -> MicrosoftAuthenticator {
+        MicrosoftAuthenticator::new("client").with_endpoints(MicrosoftEndpoints {
+            oauth: server.url.clone(),
+            xbox_user: server.url.clone(),
+            xsts: server.url.clone(),
+            minecraft: server.url.clone(),
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn device_code_login() {
+        let server = services(86400).await;
+        let codes = Arc::new(AtomicUsize::new(0));
+        let shown = Arc::clone(&codes);
+
+        let authenticator = authenticator(&server).on_device_code(move |code| {
+            assert_eq!(code.user_code, "ABCD-1234");
+            shown.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let session = authenticator.authenticate().await.unwrap();
+
+        assert_eq!(session.username, "Notch");
+        assert_eq!(session.uuid, "069a79f444e94726a5befca90e38aaf5");
+        assert_eq!(session.access_token, "minecraft");
+        assert_eq!(codes.load(Ordering::SeqCst), 1);
+        assert_eq!(server.requests_to("/token").len(), 2);
+
+        let xbox = &server.requests_to("/user/authenticate")[0];
+        assert_eq!(xbox.method, "POST");
+        assert!(xbox.body.contains("d=microsoft"));
+
+        let xsts = &server.requests_to("/authentication/login_with_xbox")[0];
+        assert!(xsts.body.contains("XBL3.0 x=hash;xsts"));
+
+        let profile = &server.requests_to("/minecraft/profile")[0];
+        assert_eq!(profile.header("authorization"), Some("Bearer minecraft"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reuses_the_session() {
+        let server = services(86400).await;
+        let authenticator = authenticator(&server);
+
+        authenticator.authenticate().await.unwrap();
+        authenticator.authenticate().await.unwrap();
+
+        assert_eq!(server.requests_to("/devicecode").len(), 1);
+        assert_eq!(server.requests_to("/minecraft/profile").len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refreshes_an_expired_session() {
+        let server = services(0).await;
+        let authenticator = authenticator(&server);
+
+        authenticator.authenticate().await.unwrap();
+        let session = authenticator.authenticate().await.unwrap();
+
+        assert_eq!(session.username, "Notch");
+        assert_eq!(server.requests_to("/devicecode").len(), 1);
+
+        let refresh = server.requests_to("/token").pop().unwrap();
+        assert!(refresh.body.contains("grant_type=refresh_token"));
+        assert!(refresh.body.contains("refresh_token=refresh"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_login_errors() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/devicecode" => {
+                let code = json!({
+                    "user_code": "ABCD-1234",
+                    "verification_uri": "https://microsoft.com/link",
+                    "message": "Enter ABCD-1234",
+                    "device_code": "device",
+                    "expires_in": 60,
+                    "interval": 0,
+                });
+                (200, code.to_string())
+            }
+            _ => (400, json!({ "error": "expired_token" }).to_string()),
+        })
+        .await;
+
+        let err = authenticator(&server).authenticate().await.unwrap_err();
+        assert!(err.to_string().contains("expired_token"));
+    }
+}
@@ -0,0 +1,31 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::Authenticator;
+use crate::session::Session;
+
+/// An offline-mode account, only the username is needed.
+#[derive(Debug, Clone)]
+pub struct Offline {
+    username: String,
+}
+
+impl Offline {
+    pub fn new(username: impl ToString) -> Self {
+        Self {
+            username: username.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for Offline {
+    async fn authenticate(&self) -> Result<Session> {
+        // The server picks the offline UUID and sends it back in LoginSuccess
+        Ok(Session {
+            username: self.username.clone(),
+            uuid: String::new(),
+            access_token: String::new(),
+        })
+    }
+}
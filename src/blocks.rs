@@ -0,0 +1,548 @@
+use crate::chunk::{BlockPos, BlockState};
+use crate::math::{Aabb, Vec3};
+use crate::World;
+
+pub const AIR: u16 = 0;
+pub const WATER: u16 = 8;
+pub const STATIONARY_WATER: u16 = 9;
+pub const LAVA: u16 = 10;
+pub const STATIONARY_LAVA: u16 = 11;
+pub const GLASS: u16 = 20;
+pub const BED: u16 = 26;
+pub const WEB: u16 = 30;
+pub const STONE_SLAB: u16 = 44;
+pub const CHEST: u16 = 54;
+pub const LADDER: u16 = 65;
+pub const SNOW_LAYER: u16 = 78;
+pub const ICE: u16 = 79;
+pub const CACTUS: u16 = 81;
+pub const FENCE: u16 = 85;
+pub const SOUL_SAND: u16 = 88;
+pub const CAKE: u16 = 92;
+pub const STAINED_GLASS: u16 = 95;
+pub const TRAPDOOR: u16 = 96;
+pub const IRON_BARS: u16 = 101;
+pub const GLASS_PANE: u16 = 102;
+pub const VINE: u16 = 106;
+pub const FENCE_GATE: u16 = 107;
+pub const LILY_PAD: u16 = 111;
+pub const NETHER_BRICK_FENCE: u16 = 113;
+pub const ENCHANTING_TABLE: u16 = 116;
+pub const BREWING_STAND: u16 = 117;
+pub const END_PORTAL_FRAME: u16 = 120;
+pub const WOODEN_SLAB: u16 = 126;
+pub const ENDER_CHEST: u16 = 130;
+pub const COBBLESTONE_WALL: u16 = 139;
+pub const FLOWER_POT: u16 = 140;
+pub const SKULL: u16 = 144;
+pub const ANVIL: u16 = 145;
+pub const TRAPPED_CHEST: u16 = 146;
+pub const DAYLIGHT_DETECTOR: u16 = 151;
+pub const STAINED_GLASS_PANE: u16 = 160;
+pub const SLIME: u16 = 165;
+pub const IRON_TRAPDOOR: u16 = 167;
+pub const CARPET: u16 = 171;
+pub const PACKED_ICE: u16 = 174;
+pub const INVERTED_DAYLIGHT_DETECTOR: u16 = 178;
+pub const RED_SANDSTONE_SLAB: u16 = 182;
+
+const DOORS: &[u16] = &[64, 71, 193, 194, 195, 196, 197];
+const FENCES: &[u16] = &[FENCE, 188, 189, 190, 191, 192];
+const FENCE_GATES: &[u16] = &[FENCE_GATE, 183, 184, 185, 186, 187];
+const STAIRS: &[u16] = &[
+    53, 67, 108, 109, 114, 128, 134, 135, 136, 156, 163, 164, 180,
+];
+const REDSTONE_DIODES: &[u16] = &[93, 94, 149, 150];
+
+const NO_COLLISION: &[u16] = &[
+    AIR,
+    6,
+    WATER,
+    STATIONARY_WATER,
+    LAVA,
+    STATIONARY_LAVA,
+    27,
+    28,
+    WEB,
+    31,
+    32,
+    36,
+    37,
+    38,
+    39,
+    40,
+    50,
+    51,
+    55,
+    59,
+    63,
+    66,
+    68,
+    69,
+    70,
+    72,
+    75,
+    76,
+    77,
+    83,
+    90,
+    104,
+    105,
+    VINE,
+    115,
+    119,
+    131,
+    132,
+    141,
+    142,
+    143,
+    147,
+    148,
+    157,
+    175,
+    176,
+    177,
+];
+
+pub fn is_water(state: BlockState) -> bool {
+    matches!(state.id(), WATER | STATIONARY_WATER)
+}
+
+pub fn is_lava(state: BlockState) -> bool {
+    matches!(state.id(), LAVA | STATIONARY_LAVA)
+}
+
+pub fn is_liquid(state: BlockState) -> bool {
+    is_water(state) || is_lava(state)
+}
+
+pub fn is_climbable(state: BlockState) -> bool {
+    matches!(state.id(), LADDER | VINE)
+}
+
+pub fn is_web(state: BlockState) -> bool {
+    state.id() == WEB
+}
+
+pub fn is_soul_sand(state: BlockState) -> bool {
+    state.id() == SOUL_SAND
+}
+
+pub fn is_slime(state: BlockState) -> bool {
+    state.id() == SLIME
+}
+
+pub fn is_door(state: BlockState) -> bool {
+    DOORS.contains(&state.id())
+}
+
+pub fn is_fence_like(state: BlockState) -> bool {
+    let id = state.id();
+    FENCES.contains(&id)
+        || FENCE_GATES.contains(&id)
+        || id == NETHER_BRICK_FENCE
+        || id == COBBLESTONE_WALL
+}
+
+pub fn has_collision(state: BlockState) -> bool {
+    !NO_COLLISION.contains(&state.id())
+}
+
+/// Whether the block is one of the many plain cubes, which covers anything not special cased
+/// below.
+pub fn is_full_cube(state: BlockState) -> bool {
+    let id = state.id();
+
+    has_collision(state)
+        && !is_door(state)
+        && !is_fence_like(state)
+        && !STAIRS.contains(&id)
+        && !REDSTONE_DIODES.contains(&id)
+        && !matches!(
+            id,
+            BED | STONE_SLAB
+                | CHEST
+                | LADDER
+                | SNOW_LAYER
+                | CACTUS
+                | SOUL_SAND
+                | CAKE
+                | TRAPDOOR
+                | IRON_BARS
+                | GLASS_PANE
+                | LILY_PAD
+                | ENCHANTING_TABLE
+                | BREWING_STAND
+                | END_PORTAL_FRAME
+                | WOODEN_SLAB
+                | ENDER_CHEST
+                | FLOWER_POT
+                | SKULL
+                | ANVIL
+                | TRAPPED_CHEST
+                | DAYLIGHT_DETECTOR
+                | STAINED_GLASS_PANE
+                | IRON_TRAPDOOR
+                | CARPET
+                | INVERTED_DAYLIGHT_DETECTOR
+                | RED_SANDSTONE_SLAB
+        )
+}
+
+/// How slippery the top of the block is, vanilla's `Block.slipperiness`.
+pub fn slipperiness(state: BlockState) -> f64 {
+    match state.id() {
+        ICE | PACKED_ICE => 0.98,
+        SLIME => 0.8,
+        _ => 0.6,
+    }
+}
+
+/// How much of the block a liquid fills, vanilla's `BlockLiquid.getLiquidHeightPercent`
+/// counted from the top. Falling liquids (metadata 8 and up) count as sources.
+pub fn liquid_height(state: BlockState) -> f64 {
+    let level = match state.metadata() {
+        level if level >= 8 => 0,
+        level => level,
+    };
+
+    (level as f32 + 1.0) as f64 / 9.0
+}
+
+/// The direction water at `pos` pushes entities in, vanilla's `BlockLiquid.getFlowVector`.
+/// Zero for still water and anything that isn't water.
+pub fn water_flow(world: &World, pos: BlockPos) -> Vec3 {
+    // Vanilla's `EnumFacing.Plane.HORIZONTAL` order: north, east, south, west
+    const SIDES: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+    let block = |pos: BlockPos| world.block_at(pos.x, pos.y, pos.z);
+
+    // `getEffectiveFlowDecay`, `None` when the block isn't water
+    let decay = |pos: BlockPos| {
+        let state = block(pos).filter(|&state| is_water(state))?;
+
+        Some(match state.metadata() {
+            level if level >= 8 => 0,
+            level => level as i32,
+        })
+    };
+
+    // Vanilla's `Material.isSolid`, approximated by having a collision box
+    let is_solid = |pos: BlockPos| {
+        block(pos)
+            .is_some_and(|state| !is_water(state) && state.id() != ICE && has_collision(state))
+    };
+
+    let Some(state) = block(pos).filter(|&state| is_water(state)) else {
+        return Vec3::ZERO;
+    };
+
+    let own = decay(pos).unwrap_or_default();
+    let mut flow = Vec3::ZERO;
+
+    for (dx, dz) in SIDES {
+        let side = pos.offset(dx, 0, dz);
+
+        let difference = match decay(side) {
+            Some(level) => level - own,
+            // Water pours towards an edge it can fall down
+            None if block(side).is_some_and(|state| !has_collision(state)) => {
+                match decay(side.offset(0, -1, 0)) {
+                    Some(level) => level - (own - 8),
+                    None => continue,
+                }
+            }
+            None => continue,
+        };
+
+        flow += Vec3::new((dx * difference) as f64, 0.0, (dz * difference) as f64);
+    }
+
+    // Falling water next to a wall drags entities down
+    if state.metadata() >= 8 {
+        let against_wall = SIDES.into_iter().any(|(dx, dz)| {
+            let side = pos.offset(dx, 0, dz);
+            is_solid(side) || is_solid(side.offset(0, 1, 0))
+        });
+
+        if against_wall {
+            flow = flow.normalize() + Vec3::new(0.0, -6.0, 0.0);
+        }
+    }
+
+    flow.normalize()
+}
+
+fn cube(min_x: f64, min_y: f64, min_z: f64, max_x: f64, max_y: f64, max_z: f64) -> Aabb {
+    Aabb::new(
+        Vec3::new(min_x, min_y, min_z),
+        Vec3::new(max_x, max_y, max_z),
+    )
+}
+
+/// Returns the collision boxes of the block at `pos` in world coordinates, following the 1.8
+/// `addCollisionBoxesToList` implementations. Some blocks look at their neighbours (fences,
+/// panes, doors), so the world is needed as well.
+pub fn collision_boxes(world: &World, pos: BlockPos, state: BlockState) -> Vec<Aabb> {
+    let origin = Vec3::new(pos.x as f64, pos.y as f64, pos.z as f64);
+
+    local_collision_boxes(world, pos, state)
+        .into_iter()
+        .map(|shape| shape.offset(origin))
+        .collect()
+}
+
+fn local_collision_boxes(world: &World, pos: BlockPos, state: BlockState) -> Vec<Aabb> {
+    const THIN: f64 = 0.1875;
+
+    let id = state.id();
+    let meta = state.metadata();
+
+    if !has_collision(state) {
+        return vec![];
+    }
+
+    if is_full_cube(state) {
+        return vec![cube(0.0, 0.0, 0.0, 1.0, 1.0, 1.0)];
+    }
+
+    if STAIRS.contains(&id) {
+        let (base, step) = match meta & 4 {
+            0 => ((0.0, 0.5), (0.5, 1.0)),
+            _ => ((0.5, 1.0), (0.0, 0.5)),
+        };
+
+        let step = match meta & 3 {
+            0 => cube(0.5, step.0, 0.0, 1.0, step.1, 1.0),
+            1 => cube(0.0, step.0, 0.0, 0.5, step.1, 1.0),
+            2 => cube(0.0, step.0, 0.5, 1.0, step.1, 1.0),
+            _ => cube(0.0, step.0, 0.0, 1.0, step.1, 0.5),
+        };
+
+        return vec![cube(0.0, base.0, 0.0, 1.0, base.1, 1.0), step];
+    }
+
+    if FENCES.contains(&id) || id == NETHER_BRICK_FENCE {
+        return fence_boxes(world, pos);
+    }
+
+    if FENCE_GATES.contains(&id) {
+        // Open gates can be walked through
+        if meta & 4 != 0 {
+            return vec![];
+        }
+
+        return match meta & 1 {
+            0 => vec![cube(0.0, 0.0, 0.375, 1.0, 1.5, 0.625)],
+            _ => vec![cube(0.375, 0.0, 0.0, 0.625, 1.5, 1.0)],
+        };
+    }
+
+    if is_door(state) {
+        return door_boxes(world, pos, state);
+    }
+
+    if REDSTONE_DIODES.contains(&id) {
+        return vec![cube(0.0, 0.0, 0.0, 1.0, 0.125, 1.0)];
+    }
+
+    let shape = match id {
+        STONE_SLAB | WOODEN_SLAB | RED_SANDSTONE_SLAB => match meta & 8 {
+            0 => cube(0.0, 0.0, 0.0, 1.0, 0.5, 1.0),
+            _ => cube(0.0, 0.5, 0.0, 1.0, 1.0, 1.0),
+        },
+        BED => cube(0.0, 0.0, 0.0, 1.0, 0.5625, 1.0),
+        CHEST | TRAPPED_CHEST | ENDER_CHEST => cube(0.0625, 0.0, 0.0625, 0.9375, 0.875, 0.9375),
+        LADDER => match meta {
+            2 => cube(0.0, 0.0, 0.875, 1.0, 1.0, 1.0),
+            3 => cube(0.0, 0.0, 0.0, 1.0, 1.0, 0.125),
+            4 => cube(0.875, 0.0, 0.0, 1.0, 1.0, 1.0),
+            _ => cube(0.0, 0.0, 0.0, 0.125, 1.0, 1.0),
+        },
+        SNOW_LAYER => {
+            let height = (meta & 7) as f64 * 0.125;
+
+            // A single layer has no collision box at all
+            if height == 0.0 {
+                return vec![];
+            }
+
+            cube(0.0, 0.0, 0.0, 1.0, height, 1.0)
+        }
+        CACTUS => cube(0.0625, 0.0, 0.0625, 0.9375, 0.9375, 0.9375),
+        SOUL_SAND => cube(0.0, 0.0, 0.0, 1.0, 0.875, 1.0),
+        CAKE => cube(0.0625, 0.0, 0.0625, 0.9375, 0.5, 0.9375),
+        TRAPDOOR | IRON_TRAPDOOR => match (meta & 4 != 0, meta & 3) {
+            (false, _) if meta & 8 == 0 => cube(0.0, 0.0, 0.0, 1.0, THIN, 1.0),
+            (false, _) => cube(0.0, 1.0 - THIN, 0.0, 1.0, 1.0, 1.0),
+            (true, 0) => cube(0.0, 0.0, 1.0 - THIN, 1.0, 1.0, 1.0),
+            (true, 1) => cube(0.0, 0.0, 0.0, 1.0, 1.0, THIN),
+            (true, 2) => cube(1.0 - THIN, 0.0, 0.0, 1.0, 1.0, 1.0),
+            (true, _) => cube(0.0, 0.0, 0.0, THIN, 1.0, 1.0),
+        },
+        IRON_BARS | GLASS_PANE | STAINED_GLASS_PANE => return pane_boxes(world, pos),
+        LILY_PAD => cube(0.0, 0.0, 0.0, 1.0, 0.015625, 1.0),
+        ENCHANTING_TABLE => cube(0.0, 0.0, 0.0, 1.0, 0.75, 1.0),
+        BREWING_STAND => {
+            return vec![
+                cube(0.4375, 0.0, 0.4375, 0.5625, 0.875, 0.5625),
+                cube(0.0, 0.0, 0.0, 1.0, 0.125, 1.0),
+            ];
+        }
+        END_PORTAL_FRAME => cube(0.0, 0.0, 0.0, 1.0, 0.8125, 1.0),
+        COBBLESTONE_WALL => return wall_boxes(world, pos),
+        FLOWER_POT => cube(0.3125, 0.0, 0.3125, 0.6875, 0.375, 0.6875),
+        SKULL => cube(0.25, 0.0, 0.25, 0.75, 0.5, 0.75),
+        ANVIL => match meta & 1 {
+            0 => cube(0.125, 0.0, 0.0, 0.875, 1.0, 1.0),
+            _ => cube(0.0, 0.0, 0.125, 1.0, 1.0, 0.875),
+        },
+        DAYLIGHT_DETECTOR | INVERTED_DAYLIGHT_DETECTOR => cube(0.0, 0.0, 0.0, 1.0, 0.375, 1.0),
+        CARPET => cube(0.0, 0.0, 0.0, 1.0, 0.0625, 1.0),
+        _ => cube(0.0, 0.0, 0.0, 1.0, 1.0, 1.0),
+    };
+
+    vec![shape]
+}
+
+/// Returns which horizontal neighbours (north, south, west, east) satisfy `connects`.
+fn neighbours(
+    world: &World,
+    pos: BlockPos,
+    connects: impl Fn(BlockState) -> bool,
+) -> (bool, bool, bool, bool) {
+    let check = |dx, dz| {
+        let neighbour = pos.offset(dx, 0, dz);
+        world
+            .block_at(neighbour.x, neighbour.y, neighbour.z)
+            .is_some_and(&connects)
+    };
+
+    (check(0, -1), check(0, 1), check(-1, 0), check(1, 0))
+}
+
+fn fence_boxes(world: &World, pos: BlockPos) -> Vec<Aabb> {
+    let (north, south, west, east) = neighbours(world, pos, |neighbour| {
+        (is_fence_like(neighbour) && neighbour.id() != COBBLESTONE_WALL) || is_full_cube(neighbour)
+    });
+
+    let mut boxes = vec![];
+
+    if north || south {
+        let min_z = if north { 0.0 } else { 0.375 };
+        let max_z = if south { 1.0 } else { 0.625 };
+        boxes.push(cube(0.375, 0.0, min_z, 0.625, 1.5, max_z));
+    }
+
+    if west || east || !north && !south {
+        let min_x = if west { 0.0 } else { 0.375 };
+        let max_x = if east { 1.0 } else { 0.625 };
+        boxes.push(cube(min_x, 0.0, 0.375, max_x, 1.5, 0.625));
+    }
+
+    boxes
+}
+
+fn wall_boxes(world: &World, pos: BlockPos) -> Vec<Aabb> {
+    let (north, south, west, east) = neighbours(world, pos, |neighbour| {
+        neighbour.id() == COBBLESTONE_WALL
+            || FENCE_GATES.contains(&neighbour.id())
+            || is_full_cube(neighbour)
+    });
+
+    let (mut min_x, mut max_x, mut min_z, mut max_z) = (0.25, 0.75, 0.25, 0.75);
+
+    if north {
+        min_z = 0.0;
+    }
+    if south {
+        max_z = 1.0;
+    }
+    if west {
+        min_x = 0.0;
+    }
+    if east {
+        max_x = 1.0;
+    }
+
+    // Straight walls have no post and are thinner
+    if north && south && !west && !east {
+        (min_x, max_x) = (0.3125, 0.6875);
+    } else if !north && !south && west && east {
+        (min_z, max_z) = (0.3125, 0.6875);
+    }
+
+    vec![cube(min_x, 0.0, min_z, max_x, 1.5, max_z)]
+}
+
+fn pane_boxes(world: &World, pos: BlockPos) -> Vec<Aabb> {
+    let (north, south, west, east) = neighbours(world, pos, |neighbour| {
+        matches!(
+            neighbour.id(),
+            GLASS | STAINED_GLASS | IRON_BARS | GLASS_PANE | STAINED_GLASS_PANE
+        ) || is_full_cube(neighbour)
+    });
+
+    let connected = north || south || west || east;
+    let mut boxes = vec![];
+
+    if (!west || !east) && connected {
+        if west {
+            boxes.push(cube(0.0, 0.0, 0.4375, 0.5, 1.0, 0.5625));
+        } else if east {
+            boxes.push(cube(0.5, 0.0, 0.4375, 1.0, 1.0, 0.5625));
+        }
+    } else {
+        boxes.push(cube(0.0, 0.0, 0.4375, 1.0, 1.0, 0.5625));
+    }
+
+    if (!north || !south) && connected {
+        if north {
+            boxes.push(cube(0.4375, 0.0, 0.0, 0.5625, 1.0, 0.5));
+        } else if south {
+            boxes.push(cube(0.4375, 0.0, 0.5, 0.5625, 1.0, 1.0));
+        }
+    } else {
+        boxes.push(cube(0.4375, 0.0, 0.0, 0.5625, 1.0, 1.0));
+    }
+
+    boxes
+}
+
+fn door_boxes(world: &World, pos: BlockPos, state: BlockState) -> Vec<Aabb> {
+    const THICKNESS: f64 = 0.1875;
+
+    // The lower half holds the facing and open bits, the upper half holds the hinge side
+    let (lower, upper) = match state.metadata() & 8 {
+        0 => (state, world.block_at(pos.x, pos.y + 1, pos.z)),
+        _ => (
+            world
+                .block_at(pos.x, pos.y - 1, pos.z)
+                .unwrap_or(BlockState::AIR),
+            Some(state),
+        ),
+    };
+
+    let facing = lower.metadata() & 3;
+    let open = lower.metadata() & 4 != 0;
+    let hinge = upper.is_some_and(|upper| upper.metadata() & 1 != 0);
+
+    let far = 1.0 - THICKNESS;
+
+    let shape = match (open, facing, hinge) {
+        (false, 0, _) => cube(0.0, 0.0, 0.0, THICKNESS, 1.0, 1.0),
+        (false, 1, _) => cube(0.0, 0.0, 0.0, 1.0, 1.0, THICKNESS),
+        (false, 2, _) => cube(far, 0.0, 0.0, 1.0, 1.0, 1.0),
+        (false, _, _) => cube(0.0, 0.0, far, 1.0, 1.0, 1.0),
+        (true, 0, false) => cube(0.0, 0.0, 0.0, 1.0, 1.0, THICKNESS),
+        (true, 0, true) => cube(0.0, 0.0, far, 1.0, 1.0, 1.0),
+        (true, 1, false) => cube(far, 0.0, 0.0, 1.0, 1.0, 1.0),
+        (true, 1, true) => cube(0.0, 0.0, 0.0, THICKNESS, 1.0, 1.0),
+        (true, 2, false) => cube(0.0, 0.0, far, 1.0, 1.0, 1.0),
+        (true, 2, true) => cube(0.0, 0.0, 0.0, 1.0, 1.0, THICKNESS),
+        (true, _, false) => cube(0.0, 0.0, 0.0, THICKNESS, 1.0, 1.0),
+        (true, _, true) => cube(far, 0.0, 0.0, 1.0, 1.0, 1.0),
+    };
+
+    vec![shape]
+}
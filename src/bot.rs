@@ -1,149 +1,391 @@
+use std::any::Any;
+use std::future::Future;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use futures::future::LocalBoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
 use gami_mc_protocol::packets::play::server::{
     ScoreboardObjectiveAction, ScoreboardPosition, TeamsAction,
 };
 use gami_mc_protocol::packets::{self, play::*, ServerPacket};
 use gami_mc_protocol::packets::{Packet, Packets};
 use gami_mc_protocol::registry::tcp::State;
-use gami_mc_protocol::registry::EntityKind;
+use gami_mc_protocol::registry::{Dimension, EntityKind};
 use tokio::time;
 use tokio::{io::AsyncWriteExt, net::TcpStream};
 
-use crate::events::{Context, Dispatchable, EventHandlers, PacketHandler};
+use crate::address::{DnsResolver, Endpoint, ServerAddress, SrvResolver};
+use crate::auth::{Authenticator, Offline};
+use crate::cache::WorldCache;
+use crate::crypto;
+use crate::entity::{from_angle, Transform};
+use crate::events::{Dispatchable, EventHandlers, HandlerHandle, IntoEventHandler, PacketHandler};
+use crate::handle::{BotHandle, CommandQueue};
+use crate::math::Vec3;
+use crate::pathfinding::{Goal, PathExecutor, PathStatus};
+use crate::physics::{Controls, MovementTracker, MovementUpdate, PlayerPhysics};
+use crate::players::PlayerList;
+use crate::policy::{
+    DeathPolicy, DisconnectReason, Kicked, ReconnectPolicy, Reconnected, Reconnecting,
+};
+use crate::proxy::Proxy;
 use crate::scores::{Objective, Scores};
-use crate::stream::Stream;
+use crate::semantic::{
+    Death, DimensionChange, EntityDespawn, EntityMove, EntitySpawn, HealthChange, Hurt, ItemPickup,
+    PlayerJoin, PlayerLeave, Spawn,
+};
+use crate::session::{MojangSessionService, Session, SessionService};
+use crate::shared::SharedWorlds;
+use crate::status::plain_text;
+use crate::stream::{OutgoingHooks, OutgoingPacket, Stream, PROTOCOL_VERSION};
+use crate::wait::{SharedWaiters, Waiters};
+use crate::world::BlockUpdate;
 use crate::{Inventory, World};
 
-pub struct BotBuilder {
-    username: String,
+const MAX_HEALTH: f32 = 20.0;
+const MAX_FOOD: i32 = 20;
+/// How long a death message can arrive before the health update that kills the bot.
+const DEATH_MESSAGE_WINDOW: Duration = Duration::from_secs(1);
+/// How often chunks that changed are written to the world cache while connected.
+const CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct BotBuilder<S = ()> {
+    state: Option<S>,
+    authenticator: Box<dyn Authenticator>,
+    /// Parsed when the bot starts, so it may contain a port.
     host: String,
-    port: u16,
+    /// Overrides the port in `host`.
+    port: Option<u16>,
+    proxy: Option<Proxy>,
+    srv_resolver: Box<dyn SrvResolver>,
     events: EventHandlers,
-    autoreconnect: Option<Duration>,
+    reconnect_policy: ReconnectPolicy,
+    death_policy: DeathPolicy,
+    shared_worlds: Option<SharedWorlds>,
+    world_cache: Option<WorldCache>,
+    session_service: Box<dyn SessionService>,
+    outgoing_hooks: OutgoingHooks,
 }
 
 impl BotBuilder {
     pub fn new() -> Self {
         Self::default()
     }
+}
 
+impl<S: 'static> BotBuilder<S> {
+    /// Gives sync handlers mutable access to `state` through `Context::state`.
+    ///
+    /// The state is lent to one handler at a time, which has two consequences:
+    /// - Async handlers can't reach it, `AsyncContext` only has the payload and a
+    ///   `BotHandle`. Share an `Rc<RefCell<_>>` with the closure instead.
+    /// - Events dispatched from inside a sync handler, e.g. by a bot method it calls, skip
+    ///   every sync handler since the state is already borrowed. Async handlers still run.
+    pub fn with_state(state: S) -> Self {
+        Self {
+            state: Some(state),
+            authenticator: Box::new(Offline::new("minecraft_bot_1")),
+            host: "127.0.0.1".to_string(),
+            port: None,
+            proxy: None,
+            srv_resolver: Box::new(DnsResolver::default()),
+            events: EventHandlers::default(),
+            reconnect_policy: ReconnectPolicy::default(),
+            death_policy: DeathPolicy::default(),
+            shared_worlds: None,
+            world_cache: None,
+            session_service: Box::new(MojangSessionService::default()),
+            outgoing_hooks: OutgoingHooks::default(),
+        }
+    }
+
+    /// Logs in with an offline-mode account.
     pub fn with_username(mut self, username: impl ToString) -> Self {
-        self.username = username.to_string();
+        self.authenticator = Box::new(Offline::new(username));
+        self
+    }
+
+    /// Selects how the account is authenticated, e.g. `TokenCache` for Microsoft accounts.
+    pub fn with_authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.authenticator = Box::new(authenticator);
         self
     }
 
+    /// Accepts `host[:port]`. Without a port, the `_minecraft._tcp` SRV record is used like
+    /// the vanilla client does, falling back to 25565.
     pub fn with_host(mut self, host: impl ToString) -> Self {
         self.host = host.to_string();
         self
     }
 
     pub fn with_port(mut self, port: u16) -> Self {
-        self.port = port;
+        self.port = Some(port);
         self
     }
 
-    pub fn with_autoreconnect(mut self, delay: Option<Duration>) -> Self {
-        self.autoreconnect = delay;
+    /// Tunnels every connection to the server through `proxy`, status pings included.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
         self
     }
 
-    pub async fn run(self) -> anyhow::Result<()> {
-        'run: loop {
-            let addr = format!("{}:{}", self.host, self.port);
+    /// Replaces the DNS lookup of SRV records, e.g. with a mock.
+    pub fn with_srv_resolver(mut self, resolver: impl SrvResolver + 'static) -> Self {
+        self.srv_resolver = Box::new(resolver);
+        self
+    }
 
-            let mut stream = match TcpStream::connect(addr).await {
-                Ok(stream) => stream,
-                Err(err) => {
-                    if let Some(delay) = self.autoreconnect {
-                        time::sleep(delay).await;
-                        continue;
-                    }
-                    return Err(err.into());
-                }
-            };
+    /// Reconnects with an exponential backoff by default, see `ReconnectPolicy::never` to
+    /// stop at the first disconnection instead.
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
 
-            stream.set_nodelay(true)?;
+    /// Respawns right away by default.
+    pub fn with_death_policy(mut self, policy: DeathPolicy) -> Self {
+        self.death_policy = policy;
+        self
+    }
 
-            println!("Setting protocol...");
-            self.set_protocol(&mut stream).await?;
+    /// Stores chunks in `worlds` so every bot built with the same `SharedWorlds` and
+    /// connected to the same server keeps a single copy of them.
+    pub fn with_shared_worlds(mut self, worlds: SharedWorlds) -> Self {
+        self.shared_worlds = Some(worlds);
+        self
+    }
 
-            println!("Logging in...");
-            self.login(&mut stream).await?;
+    /// Saves received chunks to disk and loads them back around the bot, even after a restart.
+    pub fn with_world_cache(mut self, cache: WorldCache) -> Self {
+        self.world_cache = Some(cache);
+        self
+    }
 
-            let mut stream = Stream::new(stream);
-            let mut packets = vec![];
-            let mut uuid = String::new();
-            let mut entity_id = None;
-            let mut game_mode = None;
+    pub fn with_session_service(mut self, service: impl SessionService + 'static) -> Self {
+        self.session_service = Box::new(service);
+        self
+    }
 
-            println!("Waiting for entity spawn...");
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let events = Rc::new(std::mem::take(&mut self.events));
+        let mut state = self
+            .state
+            .take()
+            .map(|state| Box::new(state) as Box<dyn Any>);
 
-            while entity_id.is_none() {
-                let mut new_packets = stream.read_packets().await?;
+        let mut address = ServerAddress::parse(&self.host)?;
+        address.port = self.port.or(address.port);
 
-                for packet in &new_packets {
-                    match packet {
-                        Packets::LoginSuccess(data) => {
-                            uuid = data.uuid.clone();
-                        }
+        // Reconnects in a row without a stable session
+        let mut attempts = 0;
+        // Kept until the next connection so `Reconnecting` handlers can inspect it
+        let mut previous: Option<Bot> = None;
 
-                        Packets::JoinGame(data) => {
-                            entity_id = Some(data.entity_id);
-                            game_mode = Some(data.game_mode);
-                        }
+        loop {
+            // Resolved on every connection in case the SRV record changed
+            let endpoint = address.resolve(&*self.srv_resolver).await;
 
-                        Packets::Disconnect(data) => {
-                            if let Some(delay) = self.autoreconnect {
-                                time::sleep(delay).await;
-                                continue 'run;
-                            }
+            // Authenticate on every connection so expired tokens get refreshed
+            let session = match self.authenticator.authenticate().await {
+                Ok(session) => session,
+                Err(err) => {
+                    self.backoff(&mut attempts, previous.as_mut(), err).await?;
+                    continue;
+                }
+            };
 
-                            return Err(anyhow::anyhow!(
-                                "Disconnected from server: {:?}",
-                                data.reason
-                            ));
-                        }
-                        _ => {}
-                    }
+            let joined = match self.join(&endpoint, &session).await {
+                Ok(joined) => joined,
+                Err(err) => {
+                    self.backoff(&mut attempts, previous.as_mut(), err).await?;
+                    continue;
                 }
+            };
 
-                packets.append(&mut new_packets);
+            let reconnected = previous.is_some();
+
+            // Keep the state for the new connection
+            if let Some(mut bot) = previous.take() {
+                state = bot.state.take();
             }
 
             let mut bot = Bot {
-                username: &self.username,
-                tcp: stream,
-                events: &self.events,
+                username: session.username,
+                tcp: joined.stream,
+                events: Rc::clone(&events),
+                server: endpoint.to_string(),
+                shared_worlds: self.shared_worlds.clone(),
+                world_cache: self.world_cache.clone(),
                 world: World::default(),
-                uuid,
-                entity_id: entity_id.unwrap(),
-                game_mode: game_mode.unwrap(),
+                uuid: joined.uuid,
+                entity_id: joined.entity_id,
+                game_mode: joined.game_mode,
                 scores: Scores::default(),
+                players: PlayerList::default(),
+                health: MAX_HEALTH,
+                food: MAX_FOOD,
+                saturation: 0.0,
+                death_policy: self.death_policy,
+                death_message: None,
+                disconnected: false,
                 inventory: Inventory::default(),
+                controls: Controls::default(),
+                physics: None,
+                movement: MovementTracker::default(),
+                pathfinder: None,
+                commands: CommandQueue::default(),
+                waiters: SharedWaiters::default(),
+                tasks: FuturesUnordered::new(),
+                state: state.take(),
+            };
+
+            let connected_at = Instant::now();
+
+            let result = match bot.handle_packets(joined.packets).await {
+                Ok(()) => {
+                    if reconnected {
+                        Reconnected { attempts }.dispatch(&events, &mut bot);
+                    }
+
+                    bot.run().await
+                }
+                Err(e) => Err(e),
             };
 
-            bot.handle_packets(packets).await?;
+            bot.save_world_cache().await;
 
-            if let Err(e) = bot.run().await {
+            if let Err(e) = result {
                 eprintln!("Bot Error: {:?}", e);
 
-                if let Some(delay) = self.autoreconnect {
-                    time::sleep(delay).await;
-                    continue;
+                if connected_at.elapsed() >= self.reconnect_policy.stable_after() {
+                    attempts = 0;
                 }
+
+                // The error may not come from the socket, close it so the server doesn't
+                // keep the session alive and reject the next login as a duplicate
+                bot.tcp.shutdown().await;
+
+                previous = Some(bot);
+                self.backoff(&mut attempts, previous.as_mut(), e).await?;
+                continue;
             }
 
             return Ok(());
         }
     }
 
-    async fn set_protocol(&self, stream: &mut TcpStream) -> anyhow::Result<()> {
+    /// Connects and logs in, until the server sends `JoinGame`.
+    async fn join(&self, endpoint: &Endpoint, session: &Session) -> anyhow::Result<Joined> {
+        let mut stream = self.connect(endpoint).await?;
+        stream.set_nodelay(true)?;
+
+        println!("Setting protocol...");
+        self.set_protocol(&mut stream, endpoint).await?;
+
+        println!("Logging in...");
+        self.login(&mut stream, &session.username).await?;
+
+        let mut stream = Stream::new(stream, Rc::clone(&self.outgoing_hooks));
+        let mut packets = vec![];
+        let mut uuid = String::new();
+
+        println!("Waiting for entity spawn...");
+
+        loop {
+            let mut new_packets = stream.read_packets().await?;
+            let mut joined = None;
+
+            for packet in &new_packets {
+                match packet {
+                    Packets::LoginSuccess(data) => {
+                        uuid = data.uuid.clone();
+                    }
+
+                    Packets::EncryptionBegin(data) => {
+                        self.encrypt(&mut stream, session, data).await?;
+                    }
+
+                    Packets::JoinGame(data) => {
+                        joined = Some((data.entity_id, data.game_mode));
+                    }
+
+                    Packets::Disconnect(data) => {
+                        return Err(Kicked {
+                            reason: data.reason.clone(),
+                        }
+                        .into());
+                    }
+                    _ => {}
+                }
+            }
+
+            packets.append(&mut new_packets);
+
+            if let Some((entity_id, game_mode)) = joined {
+                return Ok(Joined {
+                    stream,
+                    packets,
+                    uuid,
+                    entity_id,
+                    game_mode,
+                });
+            }
+        }
+    }
+
+    /// Waits before the next attempt, or returns `err` if the policy gives up.
+    async fn backoff(
+        &self,
+        attempts: &mut u32,
+        previous: Option<&mut Bot>,
+        err: anyhow::Error,
+    ) -> anyhow::Result<()> {
+        *attempts += 1;
+
+        let reason = DisconnectReason::from_error(&err);
+
+        let Some(delay) = self.reconnect_policy.next_delay(*attempts, &reason) else {
+            return Err(err);
+        };
+
+        match previous {
+            Some(bot) => {
+                let events = Rc::clone(&bot.events);
+
+                let reconnecting = Reconnecting {
+                    reason,
+                    attempt: *attempts,
+                    delay,
+                };
+
+                reconnecting.dispatch(&events, bot);
+                bot.wait_disconnected(delay).await;
+            }
+            None => time::sleep(delay).await,
+        }
+
+        Ok(())
+    }
+
+    async fn connect(&self, endpoint: &Endpoint) -> anyhow::Result<TcpStream> {
+        match self.proxy {
+            Some(ref proxy) => proxy.connect(&endpoint.host, endpoint.port).await,
+            None => Ok(TcpStream::connect((endpoint.host.as_str(), endpoint.port)).await?),
+        }
+    }
+
+    async fn set_protocol(
+        &self,
+        stream: &mut TcpStream,
+        endpoint: &Endpoint,
+    ) -> anyhow::Result<()> {
+        // Like vanilla, the handshake names the host actually connected to, which is the SRV
+        // target when there is one. Virtual hosts are configured for that name.
         let packet = packets::handshake::client::SetProtocol {
-            protocol_version: 47,
-            server_host: self.host.clone(),
-            server_port: self.port,
+            protocol_version: PROTOCOL_VERSION,
+            server_host: endpoint.host.clone(),
+            server_port: endpoint.port,
             next_state: State::Login,
         };
 
@@ -152,9 +394,9 @@ impl BotBuilder {
         Ok(())
     }
 
-    async fn login(&self, stream: &mut TcpStream) -> anyhow::Result<()> {
+    async fn login(&self, stream: &mut TcpStream, username: &str) -> anyhow::Result<()> {
         let packet = packets::login::client::LoginStart {
-            username: self.username.to_string(),
+            username: username.to_string(),
         };
 
         stream.write_all(&packet.serialize(-1)?).await?;
@@ -162,147 +404,513 @@ impl BotBuilder {
         Ok(())
     }
 
-    pub fn on_tick<T: Fn(&Context<'_, '_, ()>) + 'static>(&mut self, f: T) {
-        self.events.tick_handlers.push(Box::new(f))
+    async fn encrypt(
+        &self,
+        stream: &mut Stream,
+        session: &Session,
+        request: &packets::login::server::EncryptionBegin,
+    ) -> anyhow::Result<()> {
+        if session.is_offline() {
+            return Err(anyhow::anyhow!(
+                "Server is in online mode but the bot uses an offline account"
+            ));
+        }
+
+        let secret = crypto::generate_shared_secret();
+        let hash = crypto::server_hash(&request.server_id, &secret, &request.public_key);
+        self.session_service.join(session, &hash).await?;
+
+        let packet = packets::login::client::EncryptionBegin {
+            shared_secret: crypto::encrypt_with_public_key(&request.public_key, &secret)?,
+            verify_token: crypto::encrypt_with_public_key(
+                &request.public_key,
+                &request.verify_token,
+            )?,
+        };
+
+        stream.send_protected_packet(&packet).await?;
+        stream.enable_encryption(&secret).await?;
+
+        Ok(())
     }
 
-    pub fn on_packet<T: ServerPacket>(&mut self, f: impl PacketHandler<T>) {
-        f.register(&mut self.events);
+    /// Runs `f` on every packet the bot sends after `LoginStart`, in registration order,
+    /// before it's serialized. Useful for logging or throttling, see `OutgoingPacket`.
+    ///
+    /// Keep alive answers, the encryption response and teleport confirmations are passed to
+    /// `f` too, but can't be canceled or modified: the server kicks bots that don't send them.
+    pub fn on_outgoing(&mut self, f: impl FnMut(&mut OutgoingPacket) + 'static) {
+        self.outgoing_hooks.borrow_mut().push(Box::new(f));
     }
 
-    pub fn on_connect<T: Fn(&Context<'_, '_, ()>) + 'static>(&mut self, f: T) {
-        self.events.on_connect_handlers.push(Box::new(f))
+    // Every `on_*` method takes either `|ctx: &mut Context<T, S>| {}` or
+    // `|ctx: AsyncContext<T>| async move {}`, and returns a handle to configure it
+
+    pub fn on_tick<M>(&mut self, f: impl IntoEventHandler<(), S, M>) -> HandlerHandle {
+        self.events.tick_handlers.push(f.into_handler())
     }
 
-    pub fn on_disconnect(&mut self, f: impl PacketHandler<server::KickDisconnect>) {
-        f.register(&mut self.events);
+    pub fn on_packet<T: ServerPacket, M>(
+        &mut self,
+        f: impl PacketHandler<T, S, M>,
+    ) -> HandlerHandle {
+        f.register(&mut self.events)
+    }
+
+    pub fn on_connect<M>(&mut self, f: impl IntoEventHandler<(), S, M>) -> HandlerHandle {
+        self.events.on_connect_handlers.push(f.into_handler())
+    }
+
+    /// Runs for every block the server changes, including blocks it resends unchanged.
+    pub fn on_block_update<M>(
+        &mut self,
+        f: impl IntoEventHandler<BlockUpdate, S, M>,
+    ) -> HandlerHandle {
+        self.events.block_update_handlers.push(f.into_handler())
+    }
+
+    pub fn on_death<M>(&mut self, f: impl IntoEventHandler<Death, S, M>) -> HandlerHandle {
+        self.events.death_handlers.push(f.into_handler())
+    }
+
+    pub fn on_spawn<M>(&mut self, f: impl IntoEventHandler<Spawn, S, M>) -> HandlerHandle {
+        self.events.spawn_handlers.push(f.into_handler())
+    }
+
+    pub fn on_health_change<M>(
+        &mut self,
+        f: impl IntoEventHandler<HealthChange, S, M>,
+    ) -> HandlerHandle {
+        self.events.health_change_handlers.push(f.into_handler())
     }
 
-    pub fn on_chat(&mut self, f: impl PacketHandler<server::Chat>) {
-        f.register(&mut self.events);
+    pub fn on_entity_spawn<M>(
+        &mut self,
+        f: impl IntoEventHandler<EntitySpawn, S, M>,
+    ) -> HandlerHandle {
+        self.events.entity_spawn_handlers.push(f.into_handler())
     }
 
-    pub fn on_scoreboard_action(&mut self, f: impl PacketHandler<server::ScoreboardObjective>) {
-        f.register(&mut self.events);
+    pub fn on_entity_despawn<M>(
+        &mut self,
+        f: impl IntoEventHandler<EntityDespawn, S, M>,
+    ) -> HandlerHandle {
+        self.events.entity_despawn_handlers.push(f.into_handler())
     }
 
-    pub fn on_scoreboard_display(&mut self, f: impl PacketHandler<server::ScoreboardDisplay>) {
-        f.register(&mut self.events);
+    pub fn on_entity_move<M>(
+        &mut self,
+        f: impl IntoEventHandler<EntityMove, S, M>,
+    ) -> HandlerHandle {
+        self.events.entity_move_handlers.push(f.into_handler())
     }
 
-    pub fn on_teams_action(&mut self, f: impl PacketHandler<server::Teams>) {
-        f.register(&mut self.events);
+    pub fn on_player_join<M>(
+        &mut self,
+        f: impl IntoEventHandler<PlayerJoin, S, M>,
+    ) -> HandlerHandle {
+        self.events.player_join_handlers.push(f.into_handler())
+    }
+
+    pub fn on_player_leave<M>(
+        &mut self,
+        f: impl IntoEventHandler<PlayerLeave, S, M>,
+    ) -> HandlerHandle {
+        self.events.player_leave_handlers.push(f.into_handler())
+    }
+
+    pub fn on_item_pickup<M>(
+        &mut self,
+        f: impl IntoEventHandler<ItemPickup, S, M>,
+    ) -> HandlerHandle {
+        self.events.item_pickup_handlers.push(f.into_handler())
+    }
+
+    pub fn on_hurt<M>(&mut self, f: impl IntoEventHandler<Hurt, S, M>) -> HandlerHandle {
+        self.events.hurt_handlers.push(f.into_handler())
+    }
+
+    pub fn on_dimension_change<M>(
+        &mut self,
+        f: impl IntoEventHandler<DimensionChange, S, M>,
+    ) -> HandlerHandle {
+        self.events.dimension_change_handlers.push(f.into_handler())
+    }
+
+    pub fn on_reconnecting<M>(
+        &mut self,
+        f: impl IntoEventHandler<Reconnecting, S, M>,
+    ) -> HandlerHandle {
+        self.events.reconnecting_handlers.push(f.into_handler())
+    }
+
+    pub fn on_reconnected<M>(
+        &mut self,
+        f: impl IntoEventHandler<Reconnected, S, M>,
+    ) -> HandlerHandle {
+        self.events.reconnected_handlers.push(f.into_handler())
+    }
+
+    pub fn on_disconnect<M>(
+        &mut self,
+        f: impl PacketHandler<server::KickDisconnect, S, M>,
+    ) -> HandlerHandle {
+        f.register(&mut self.events)
+    }
+
+    pub fn on_chat<M>(&mut self, f: impl PacketHandler<server::Chat, S, M>) -> HandlerHandle {
+        f.register(&mut self.events)
+    }
+
+    pub fn on_scoreboard_action<M>(
+        &mut self,
+        f: impl PacketHandler<server::ScoreboardObjective, S, M>,
+    ) -> HandlerHandle {
+        f.register(&mut self.events)
+    }
+
+    pub fn on_scoreboard_display<M>(
+        &mut self,
+        f: impl PacketHandler<server::ScoreboardDisplay, S, M>,
+    ) -> HandlerHandle {
+        f.register(&mut self.events)
+    }
+
+    pub fn on_teams_action<M>(
+        &mut self,
+        f: impl PacketHandler<server::Teams, S, M>,
+    ) -> HandlerHandle {
+        f.register(&mut self.events)
     }
 }
 
-impl Default for BotBuilder {
+impl<S: Default + 'static> Default for BotBuilder<S> {
     fn default() -> Self {
-        Self {
-            username: "minecraft_bot_1".to_string(),
-            host: "127.0.0.1".to_string(),
-            port: 25565,
-            events: EventHandlers::default(),
-            autoreconnect: Some(Duration::from_secs(5)),
-        }
+        Self::with_state(S::default())
     }
 }
 
-pub struct Bot<'a> {
-    pub username: &'a String,
+/// A connection that made it through login, ready to become a `Bot`.
+struct Joined {
+    stream: Stream,
+    /// Everything received up to `JoinGame`, handled once the bot exists.
+    packets: Vec<Packets>,
+    uuid: String,
+    entity_id: i32,
+    game_mode: u8,
+}
+
+pub struct Bot {
+    pub username: String,
     tcp: Stream,
-    pub events: &'a EventHandlers,
+    pub(crate) events: Rc<EventHandlers>,
+    server: String,
+    shared_worlds: Option<SharedWorlds>,
+    world_cache: Option<WorldCache>,
     pub inventory: Inventory,
     pub world: World,
     pub uuid: String,
     pub entity_id: i32,
     pub game_mode: u8,
     pub scores: Scores,
+    pub players: PlayerList,
+    health: f32,
+    food: i32,
+    saturation: f32,
+    death_policy: DeathPolicy,
+    /// The last chat message announcing the bot's death and when it was received.
+    death_message: Option<(String, Instant)>,
+    disconnected: bool,
+    pub controls: Controls,
+    physics: Option<PlayerPhysics>,
+    movement: MovementTracker,
+    pathfinder: Option<PathExecutor>,
+    commands: CommandQueue,
+    pub(crate) waiters: SharedWaiters,
+    /// Async handlers in flight. Kept on the bot so a lost connection doesn't cancel them,
+    /// `wait_disconnected` keeps polling them until the next connection.
+    tasks: FuturesUnordered<LocalBoxFuture<'static, ()>>,
+    /// The builder's user state, taken out while sync handlers run.
+    pub(crate) state: Option<Box<dyn Any>>,
 }
 
-impl Bot<'_> {
+impl Bot {
     async fn run(&mut self) -> anyhow::Result<()> {
         let mut interval = tokio::time::interval(Duration::from_millis(50));
+        let mut cache_saves = time::interval_at(
+            time::Instant::now() + CACHE_SAVE_INTERVAL,
+            CACHE_SAVE_INTERVAL,
+        );
 
         loop {
-            interval.tick().await;
-            self.tick().await?;
+            if self.disconnected {
+                return Ok(());
+            }
+
+            // Reading races the interval so physics ticks every 50ms even on a quiet server.
+            // Unbiased, so async handlers aren't starved while packets keep arriving
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.tick().await?;
+                }
+
+                read = self.tcp.read() => {
+                    read?;
+                    let packets = self.tcp.decode_packets().await?;
+                    self.handle_packets(packets).await?;
+                }
+
+                _ = cache_saves.tick() => {
+                    self.save_world_cache().await;
+                }
+
+                Some(()) = self.tasks.next(), if !self.tasks.is_empty() => {}
+            }
+
+            self.apply_commands();
         }
     }
 
-    pub(crate) async fn tick(&mut self) -> anyhow::Result<()> {
-        let packets = self.tcp.read_packets().await?;
-        self.handle_packets(packets).await?;
+    /// Waits out `delay` after the connection is lost, still polling async handlers. Their
+    /// commands are never applied since the bot won't tick again.
+    async fn wait_disconnected(&mut self, delay: Duration) {
+        let sleep = time::sleep(delay);
+        tokio::pin!(sleep);
 
-        self.run_on_tick_events().await?;
+        loop {
+            tokio::select! {
+                _ = &mut sleep => return,
+                Some(()) = self.tasks.next(), if !self.tasks.is_empty() => {}
+            }
+        }
+    }
+
+    /// Writes the chunks that changed since the last save, so a crash doesn't lose them.
+    async fn save_world_cache(&self) {
+        if let Err(e) = self.world.save_cache().await {
+            eprintln!("[ERROR] Failed to save the world cache: {:?}", e);
+        }
+    }
+
+    /// Applies the commands queued by `BotHandle`s, in the order they were sent.
+    fn apply_commands(&mut self) {
+        loop {
+            // Commands may queue more commands, don't hold the borrow while running them
+            let Some(command) = self.commands.borrow_mut().pop_front() else {
+                break;
+            };
+
+            command(self);
+        }
+    }
 
-        // TODO: Tick Physics / Update Position
+    pub fn handle(&self) -> BotHandle {
+        BotHandle::new(&self.commands, &self.waiters)
+    }
+
+    /// See `BotHandle::wait_for`, the returned future doesn't borrow the bot.
+    pub fn wait_for<T: ServerPacket + Clone + 'static>(
+        &self,
+        predicate: impl Fn(&T) -> bool + 'static,
+        timeout: Duration,
+    ) -> impl Future<Output = anyhow::Result<T>> {
+        Waiters::register(&self.waiters, predicate, timeout)
+    }
+
+    /// The state given to `BotBuilder::with_state`, e.g. from `BotHandle::run` in async
+    /// handlers. `None` if `S` isn't its type or while sync handlers are running.
+    pub fn state_mut<S: 'static>(&mut self) -> Option<&mut S> {
+        self.state.as_mut()?.downcast_mut()
+    }
+
+    pub(crate) fn spawn(&mut self, task: LocalBoxFuture<'static, ()>) {
+        self.tasks.push(task);
+    }
+
+    pub(crate) async fn tick(&mut self) -> anyhow::Result<()> {
+        self.run_on_tick_events().await?;
+        self.tick_physics().await?;
 
         Ok(())
     }
 
     pub(crate) async fn handle_packets(&mut self, packets: Vec<Packets>) -> anyhow::Result<()> {
+        let events = Rc::clone(&self.events);
+
+        // The server clears the inventory as the bot dies, keep what it had for the event
+        let dying = !self.is_dead()
+            && packets
+                .iter()
+                .any(|packet| matches!(packet, Packets::UpdateHealth(data) if data.health <= 0.0));
+        let inventory = dying.then(|| self.inventory.clone());
+        let mut died = false;
+
         for packet in packets {
-            Dispatchable::dispatch_packet_event(&packet, self);
+            if packet.dispatch(&events, self) {
+                continue;
+            }
 
             match packet {
                 Packets::LoginSuccess(_) => {
                     self.run_on_connect_events().await?;
                 }
 
+                Packets::JoinGame(data) => {
+                    self.enter_dimension(data.dimension);
+                }
+
+                Packets::Respawn(data) => {
+                    let previous = self.world.dimension;
+                    self.physics = None;
+                    self.enter_dimension(data.dimension);
+
+                    if previous != data.dimension {
+                        let change = DimensionChange {
+                            previous,
+                            dimension: data.dimension,
+                        };
+                        change.dispatch(&events, self);
+                    }
+                }
+
+                Packets::ServerPosition(data) => {
+                    let spawned = self.physics.is_none();
+                    self.handle_teleport(&data).await?;
+
+                    if spawned {
+                        if let Some(position) = self.position() {
+                            Spawn { position }.dispatch(&events, self);
+                        }
+                    }
+                }
+
+                Packets::MapChunk(data) => {
+                    if let Err(e) = self.world.load_chunk(&data) {
+                        eprintln!("[ERROR] Failed to load chunk: {:?}", e);
+                    }
+                }
+
+                Packets::MapChunkBulk(data) => {
+                    if let Err(e) = self.world.load_chunk_bulk(&data) {
+                        eprintln!("[ERROR] Failed to load chunks: {:?}", e);
+                    }
+                }
+
+                Packets::BlockChange(data) => {
+                    if let Some(update) = self.world.apply_block_change(&data) {
+                        self.run_on_block_update_events(&[update]).await?;
+                    }
+                }
+
+                Packets::MultiBlockChange(data) => {
+                    let updates = self.world.apply_multi_block_change(&data);
+                    self.run_on_block_update_events(&updates).await?;
+                }
+
+                Packets::Explosion(data) => {
+                    let updates = self.world.apply_explosion(&data);
+                    self.run_on_block_update_events(&updates).await?;
+                }
+
+                Packets::ServerHeldItemSlot(data) => {
+                    self.inventory.main_hand = data.slot;
+                }
+
                 Packets::UpdateHealth(data) => {
-                    self.run_on_health_update_events(&data).await?;
-                }
-
-                // Packets::Entity(data) => {
-                //     self.world.spawn_entity(data.entity_id);
-                // }
-                // Packets::EntityVelocity(data) => {
-                //     self.world.spawn_entity(data.entity_id);
-                // }
-                // Packets::EntityMoveLook(data) => {
-                //     self.world.spawn_entity(data.entity_id);
-                // }
-
-                // Packets::EntityRelativeMove(data) => {
-                //     let entity = self.world.get_entity(data.entity_id)?;
-                //     if let Some(ref mut coords) = entity.coords {
-                //         coords.x += data.d_x as i32;
-                //         coords.y += data.d_y as i32;
-                //         coords.z += data.d_z as i32;
-                //     }
-                // }
-
-                // Packets::EntityHeadRotation(data) => {
-                //     let entity = self.world.get_entity(data.entity_id)?;
-                //     if let Some(ref mut coords) = entity.coords {
-                //         coords.yaw = data.head_yaw;
-                //     }
-                // }
-                // Packets::EntityTeleport(data) => {
-                //     if let Some(entity) = self.world.get_entity(data.entity_id) {
-
-                //     }
-                //     entity.coords = Some(Coordinates {
-                //         x: data.x,
-                //         y: data.y,
-                //         z: data.z,
-                //         yaw: data.yaw,
-                //         pitch: data.pitch,
-                //     });
-                // }
+                    died |= self.run_on_health_update_events(&data).await?;
+                }
+
+                Packets::ServerChat(data) => {
+                    if death_victim(&data.message).is_some_and(|victim| victim == self.username) {
+                        self.death_message = Some((data.message, Instant::now()));
+                    }
+                }
+
                 Packets::SpawnPlayer(player) => {
-                    let entity = EntityKind::from(&player);
-                    self.world.entities.insert(entity.id(), entity);
+                    let transform =
+                        Transform::new(player.x, player.y, player.z, player.yaw, player.pitch);
+                    self.spawn_entity(&events, EntityKind::from(&player), transform);
                 }
 
                 Packets::SpawnObject(object) => {
-                    let entity = EntityKind::from(&object);
-                    self.world.entities.insert(entity.id(), entity);
+                    let transform =
+                        Transform::new(object.x, object.y, object.z, object.yaw, object.pitch);
+                    self.spawn_entity(&events, EntityKind::from(&object), transform);
                 }
 
                 Packets::SpawnMob(mob) => {
-                    let entity = EntityKind::from(&mob);
-                    self.world.entities.insert(entity.id(), entity);
+                    let mut transform = Transform::new(mob.x, mob.y, mob.z, mob.yaw, mob.pitch);
+                    // Despite its name, this field holds the head yaw
+                    transform.head_yaw = from_angle(mob.head_pitch);
+                    transform.set_velocity(mob.velocity_x, mob.velocity_y, mob.velocity_z);
+                    self.spawn_entity(&events, EntityKind::from(&mob), transform);
+                }
+
+                Packets::EntityRelativeMove(data) => {
+                    self.move_entity(&events, data.entity_id, |world| {
+                        world.apply_relative_move(&data)
+                    });
+                }
+
+                Packets::EntityMoveLook(data) => {
+                    self.move_entity(&events, data.entity_id, |world| {
+                        world.apply_move_look(&data)
+                    });
+                }
+
+                Packets::EntityTeleport(data) => {
+                    self.move_entity(&events, data.entity_id, |world| world.apply_teleport(&data));
+                }
+
+                Packets::EntityLook(data) => self.world.apply_look(&data),
+                Packets::EntityHeadRotation(data) => self.world.apply_head_rotation(&data),
+                Packets::EntityVelocity(data) => self.world.apply_velocity(&data),
+
+                Packets::EntityDestroy(data) => {
+                    for &id in &data.entity_ids {
+                        let Some(&transform) = self.world.transform(id) else {
+                            continue;
+                        };
+
+                        if let Some(entity) = self.world.remove_entity(id) {
+                            let despawn = EntityDespawn {
+                                entity: Rc::new(entity),
+                                transform,
+                            };
+                            despawn.dispatch(&events, self);
+                        }
+                    }
+                }
+
+                Packets::Collect(data) => {
+                    let pickup = ItemPickup {
+                        collected_id: data.collected_entity_id,
+                        collector_id: data.collector_entity_id,
+                    };
+                    pickup.dispatch(&events, self);
+                }
+
+                Packets::EntityStatus(data) => {
+                    const HURT: i8 = 2;
+
+                    if data.entity_status == HURT {
+                        Hurt {
+                            entity_id: data.entity_id,
+                        }
+                        .dispatch(&events, self);
+                    }
+                }
+
+                Packets::PlayerInfo(data) => {
+                    let (joined, left) = self.players.apply(&data);
+
+                    for player in joined {
+                        PlayerJoin { player }.dispatch(&events, self);
+                    }
+
+                    for player in left {
+                        PlayerLeave { player }.dispatch(&events, self);
+                    }
                 }
 
                 Packets::EntityMetadata(data) => {
@@ -382,10 +990,10 @@ impl Bot<'_> {
                 },
 
                 Packets::KickDisconnect(data) => {
-                    return Err(anyhow::anyhow!(
-                        "Disconnected from server: {:?}",
-                        data.reason
-                    ));
+                    return Err(Kicked {
+                        reason: data.reason.clone(),
+                    }
+                    .into());
                 }
 
                 Packets::SetSlot(data) => {
@@ -409,18 +1017,119 @@ impl Bot<'_> {
             }
         }
 
+        if died {
+            let inventory = inventory.unwrap_or_else(|| self.inventory.clone());
+            self.run_on_death_events(inventory).await?;
+        }
+
         Ok(())
     }
 
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub fn entity_id(&self) -> i32 {
+        self.entity_id
+    }
+
+    pub fn game_mode(&self) -> u8 {
+        self.game_mode
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn health(&self) -> f32 {
+        self.health
+    }
+
+    pub fn food(&self) -> i32 {
+        self.food
+    }
+
+    pub fn saturation(&self) -> f32 {
+        self.saturation
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+
+    /// Leaves the server once the current tick or task is done, `BotBuilder::run` then
+    /// returns without reconnecting.
+    pub fn disconnect(&mut self) {
+        self.disconnected = true;
+    }
+
+    /// The bot's feet position, `None` until the server has sent the first teleport.
+    pub fn position(&self) -> Option<Vec3> {
+        self.physics.as_ref().map(|physics| physics.position)
+    }
+
+    pub fn eye_position(&self) -> Option<Vec3> {
+        self.physics.as_ref().map(|physics| physics.eye_position())
+    }
+
+    /// Returns the bot's `(yaw, pitch)` in degrees.
+    pub fn rotation(&self) -> Option<(f32, f32)> {
+        self.physics
+            .as_ref()
+            .map(|physics| (physics.yaw, physics.pitch))
+    }
+
+    pub fn physics(&self) -> Option<&PlayerPhysics> {
+        self.physics.as_ref()
+    }
+
+    /// Starts walking towards the goal, replacing any previous one.
+    pub fn goto(&mut self, goal: impl Goal + 'static) {
+        self.pathfinder = Some(PathExecutor::new(goal));
+    }
+
+    pub fn stop_pathing(&mut self) {
+        self.pathfinder = None;
+        self.controls = Controls::default();
+    }
+
+    pub fn is_pathing(&self) -> bool {
+        self.pathfinder.is_some()
+    }
+
     // TODO: Downcast to inner Entity
     pub fn entity(&self) -> &EntityKind {
         &self.world.entities[&self.entity_id]
     }
 
-    pub async fn respawn(&mut self) -> anyhow::Result<()> {
+    pub fn respawn(&mut self) {
         let packet = packets::play::client::ClientCommand::respawn();
-        self.tcp.send_packet(&packet).await?;
-        Ok(())
+
+        if let Err(e) = self.tcp.send_packet_sync(&packet) {
+            eprintln!("[ERROR] Failed to send respawn packet: {:?}", e);
+        };
+    }
+
+    /// Selects one of the 9 hotbar slots as the held item.
+    pub fn select_slot(&mut self, slot: i8) {
+        if !(0..9).contains(&slot) {
+            eprintln!("[WARN] Invalid hotbar slot {slot}, canceling...");
+            return;
+        }
+
+        self.inventory.main_hand = slot;
+
+        let packet = client::HeldItemSlot {
+            slot_id: slot as i16,
+        };
+
+        if let Err(e) = self.tcp.send_packet_sync(&packet) {
+            eprintln!("[ERROR] Failed to send held item packet: {:?}", e);
+        };
     }
 
     pub fn chat(&self, message: &str) {
@@ -459,6 +1168,183 @@ impl Bot<'_> {
         };
     }
 
+    fn spawn_entity(&mut self, events: &EventHandlers, entity: EntityKind, transform: Transform) {
+        let entity_id = entity.id();
+        self.world.spawn_entity(entity, transform);
+
+        EntitySpawn {
+            entity_id,
+            transform,
+        }
+        .dispatch(events, self);
+    }
+
+    fn move_entity(
+        &mut self,
+        events: &EventHandlers,
+        entity_id: i32,
+        apply: impl FnOnce(&mut World),
+    ) {
+        let Some(&previous) = self.world.transform(entity_id) else {
+            return;
+        };
+
+        apply(&mut self.world);
+
+        let Some(&transform) = self.world.transform(entity_id) else {
+            return;
+        };
+
+        if transform.position != previous.position {
+            let update = EntityMove {
+                entity_id,
+                previous,
+                transform,
+            };
+            update.dispatch(events, self);
+        }
+    }
+
+    fn enter_dimension(&mut self, dimension: Dimension) {
+        self.world.clear();
+        self.world.dimension = dimension;
+
+        if let Some(ref worlds) = self.shared_worlds {
+            self.world.attach(worlds.get(&self.server, dimension));
+        }
+
+        let cache = self
+            .world_cache
+            .as_ref()
+            .map(|cache| cache.scoped(&self.server, dimension));
+        self.world.set_cache(cache);
+    }
+
+    async fn handle_teleport(&mut self, data: &server::Position) -> anyhow::Result<()> {
+        let physics = self.physics.get_or_insert_with(PlayerPhysics::default);
+        let position = Vec3::new(data.x, data.y, data.z);
+        physics.teleport(position, data.yaw, data.pitch, data.flags as u8);
+
+        // The server waits for this exact packet before accepting any other movement
+        let packet = client::PositionLook {
+            x: physics.position.x,
+            y: physics.position.y,
+            z: physics.position.z,
+            yaw: physics.yaw,
+            pitch: physics.pitch,
+            on_ground: false,
+        };
+
+        self.tcp.send_protected_packet(&packet).await?;
+
+        Ok(())
+    }
+
+    async fn tick_physics(&mut self) -> anyhow::Result<()> {
+        const START_SNEAKING: i32 = 0;
+        const STOP_SNEAKING: i32 = 1;
+        const START_SPRINTING: i32 = 3;
+        const STOP_SPRINTING: i32 = 4;
+
+        // Nothing to simulate until the server tells us where we are
+        let Some(ref mut physics) = self.physics else {
+            return Ok(());
+        };
+
+        let (cx, cz) = (
+            (physics.position.x.floor() as i32) >> 4,
+            (physics.position.z.floor() as i32) >> 4,
+        );
+        self.world.load_cached_around(cx, cz).await;
+
+        if let Some(ref mut pathfinder) = self.pathfinder {
+            let status = pathfinder.tick(&self.world, physics, &mut self.controls);
+
+            if status != PathStatus::Moving {
+                if status == PathStatus::NoPath {
+                    eprintln!("[WARN] No path found, stopping pathfinder...");
+                }
+
+                self.pathfinder = None;
+                self.controls = Controls::default();
+            }
+        }
+
+        physics.tick(&self.world, &self.controls);
+
+        if physics.sprinting != self.movement.sprinting {
+            self.movement.sprinting = physics.sprinting;
+
+            let action_id = match physics.sprinting {
+                true => START_SPRINTING,
+                false => STOP_SPRINTING,
+            };
+
+            let packet = client::EntityAction {
+                entity_id: self.entity_id,
+                action_id,
+                jump_boost: 0,
+            };
+
+            self.tcp.send_packet(&packet).await?;
+        }
+
+        if physics.sneaking != self.movement.sneaking {
+            self.movement.sneaking = physics.sneaking;
+
+            let action_id = match physics.sneaking {
+                true => START_SNEAKING,
+                false => STOP_SNEAKING,
+            };
+
+            let packet = client::EntityAction {
+                entity_id: self.entity_id,
+                action_id,
+                jump_boost: 0,
+            };
+
+            self.tcp.send_packet(&packet).await?;
+        }
+
+        let Vec3 { x, y, z } = physics.position;
+        let (yaw, pitch, on_ground) = (physics.yaw, physics.pitch, physics.on_ground);
+
+        match self.movement.update(physics) {
+            MovementUpdate::PositionLook => {
+                let packet = client::PositionLook {
+                    x,
+                    y,
+                    z,
+                    yaw,
+                    pitch,
+                    on_ground,
+                };
+                self.tcp.send_packet(&packet).await?;
+            }
+
+            MovementUpdate::Position => {
+                let packet = client::Position { x, y, z, on_ground };
+                self.tcp.send_packet(&packet).await?;
+            }
+
+            MovementUpdate::Look => {
+                let packet = client::Look {
+                    yaw,
+                    pitch,
+                    on_ground,
+                };
+                self.tcp.send_packet(&packet).await?;
+            }
+
+            MovementUpdate::Flying => {
+                let packet = client::Flying { on_ground };
+                self.tcp.send_packet(&packet).await?;
+            }
+        }
+
+        Ok(())
+    }
+
     async fn send_settings(&mut self) -> anyhow::Result<()> {
         let packet = client::ClientSettings::default();
         self.tcp.send_packet(&packet).await?;
@@ -469,42 +1355,180 @@ impl Bot<'_> {
     async fn run_on_connect_events(&mut self) -> anyhow::Result<()> {
         self.send_settings().await?;
 
-        self.events.on_connect_handlers.iter().for_each(|e| {
-            e(&Context {
-                bot: self,
-                payload: &(),
-            })
-        });
+        let events = Rc::clone(&self.events);
+        EventHandlers::run(&events.on_connect_handlers, self, &());
 
         Ok(())
     }
 
     async fn run_on_tick_events(&mut self) -> anyhow::Result<()> {
-        self.events.tick_handlers.iter().for_each(|e| {
-            e(&Context {
-                bot: self,
-                payload: &(),
-            })
-        });
+        let events = Rc::clone(&self.events);
+        EventHandlers::run(&events.tick_handlers, self, &());
+
+        Ok(())
+    }
+
+    async fn run_on_block_update_events(&mut self, updates: &[BlockUpdate]) -> anyhow::Result<()> {
+        let events = Rc::clone(&self.events);
+
+        for update in updates {
+            if let Some(ref mut pathfinder) = self.pathfinder {
+                pathfinder.on_block_update(update);
+            }
+
+            // Not filtered on `old != new`, with shared chunks that would drop every update
+            // another bot applied first
+            update.dispatch(&events, self);
+        }
 
         Ok(())
     }
 
-    async fn run_on_death_events(&mut self) -> anyhow::Result<()> {
-        // TODO: Add AutoRespawn flag
-        self.respawn().await?;
+    async fn run_on_death_events(&mut self, inventory: Inventory) -> anyhow::Result<()> {
+        let events = Rc::clone(&self.events);
+
+        // Usually sent right before the health update, sometimes in the same batch after it
+        let message = self
+            .death_message
+            .take()
+            .filter(|(_, received)| received.elapsed() < DEATH_MESSAGE_WINDOW)
+            .map(|(message, _)| message);
+
+        let death = Death {
+            position: self.position(),
+            message,
+            inventory,
+        };
+        death.dispatch(&events, self);
+
+        match self.death_policy {
+            DeathPolicy::Respawn => self.respawn(),
+            DeathPolicy::RespawnAfter(delay) => {
+                let handle = self.handle();
+
+                self.spawn(Box::pin(async move {
+                    time::sleep(delay).await;
+
+                    // A handler may have respawned the bot in the meantime
+                    let _ = handle
+                        .run(|bot| {
+                            if bot.is_dead() {
+                                bot.respawn();
+                            }
+                        })
+                        .await;
+                }));
+            }
+            DeathPolicy::Stay => {}
+            DeathPolicy::Disconnect => self.disconnect(),
+        }
 
         Ok(())
     }
 
+    /// Returns `true` if the update killed the bot.
     async fn run_on_health_update_events(
         &mut self,
         data: &server::UpdateHealth,
-    ) -> anyhow::Result<()> {
-        if data.health <= 0.0 {
-            self.run_on_death_events().await?;
+    ) -> anyhow::Result<bool> {
+        let previous = self.health;
+        self.health = data.health;
+        self.food = data.food;
+        self.saturation = data.food_saturation;
+
+        if previous != data.health {
+            let events = Rc::clone(&self.events);
+            let change = HealthChange {
+                previous,
+                health: data.health,
+                food: data.food,
+                saturation: data.food_saturation,
+            };
+            change.dispatch(&events, self);
         }
 
-        Ok(())
+        // The server keeps resending 0 until the bot respawns
+        Ok(data.health <= 0.0 && previous > 0.0)
+    }
+}
+
+/// The player a `death.*` chat message is about, the first argument of the translation.
+fn death_victim(message: &str) -> Option<String> {
+    let component: serde_json::Value = serde_json::from_str(message).ok()?;
+
+    let translate = component.get("translate")?.as_str()?;
+
+    if !translate.starts_with("death.") {
+        return None;
+    }
+
+    Some(plain_text(component.get("with")?.get(0)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn death_victims() {
+        let cases = [
+            (
+                r#"{"translate":"death.attack.player","with":[{"text":"Steve"},{"text":"Alex"}]}"#,
+                Some("Steve"),
+            ),
+            (
+                r#"{"translate":"death.fell.accident.generic","with":["Steve"]}"#,
+                Some("Steve"),
+            ),
+            // The bot only killed someone
+            (
+                r#"{"translate":"death.attack.player","with":[{"text":"Alex"},{"text":"Steve"}]}"#,
+                Some("Alex"),
+            ),
+            (
+                r#"{"translate":"chat.type.text","with":["Steve","I died"]}"#,
+                None,
+            ),
+            (r#"{"text":"Steve died"}"#, None),
+            ("not json", None),
+        ];
+
+        for (message, expected) in cases {
+            assert_eq!(death_victim(message).as_deref(), expected, "{message}");
+        }
+    }
+
+    #[tokio::test]
+    async fn handshake_names_the_resolved_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+
+        // What an SRV record for the typed address pointed to
+        let endpoint = Endpoint::new("mc1.example.net", 25570);
+
+        BotBuilder::new()
+            .set_protocol(&mut client, &endpoint)
+            .await
+            .unwrap();
+        drop(client);
+
+        let mut sent = Vec::new();
+        server.read_to_end(&mut sent).await.unwrap();
+
+        let mut expected = vec![15];
+        expected.extend_from_slice(b"mc1.example.net");
+        expected.extend_from_slice(&25570u16.to_be_bytes());
+
+        assert!(
+            sent.windows(expected.len())
+                .any(|window| window == expected),
+            "{sent:?}"
+        );
     }
 }
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::{bail, Result};
+use gami_mc_protocol::registry::Dimension;
+
+use crate::chunk::Chunk;
+
+const MAGIC: &[u8; 4] = b"OGCC";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8;
+
+/// Chunks are grouped in 32x32 regions, one directory each, like vanilla's region files.
+const REGION_SHIFT: i32 = 5;
+const DEFAULT_RADIUS: i32 = 8;
+
+/// Chunks waiting to be written in the background, by path.
+#[derive(Debug, Default)]
+struct PendingWrites {
+    chunks: Mutex<HashMap<PathBuf, Arc<Chunk>>>,
+    /// Held while writing, so an older copy of a chunk never lands after a newer one.
+    writing: Mutex<()>,
+}
+
+/// Persists chunks to disk so they survive unloads and reconnects.
+///
+/// The layout is `<dir>/<server>/<dimension>/r.<rx>.<rz>/c.<cx>.<cz>.bin`.
+#[derive(Debug, Clone)]
+pub struct WorldCache {
+    dir: PathBuf,
+    radius: i32,
+    // Shared by every clone and scope, so a bot can read back what another one just evicted
+    pending: Arc<PendingWrites>,
+}
+
+impl WorldCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            radius: DEFAULT_RADIUS,
+            pending: Arc::default(),
+        }
+    }
+
+    /// How many chunks around the bot are kept loaded from the cache, defaults to 8.
+    pub fn with_radius(mut self, radius: i32) -> Self {
+        self.radius = radius.max(0);
+        self
+    }
+
+    pub fn radius(&self) -> i32 {
+        self.radius
+    }
+
+    /// Returns a cache pointing to the directory of one server and dimension.
+    pub fn scoped(&self, server: &str, dimension: Dimension) -> Self {
+        let is_safe = |c: char| c.is_ascii_alphanumeric() || c == '.' || c == '-';
+        let server: String = server
+            .chars()
+            .map(|c| if is_safe(c) { c } else { '_' })
+            .collect();
+
+        let dimension = format!("{:?}", dimension).to_lowercase();
+
+        Self {
+            dir: self.dir.join(server).join(dimension),
+            radius: self.radius,
+            pending: Arc::clone(&self.pending),
+        }
+    }
+
+    fn path(&self, cx: i32, cz: i32) -> PathBuf {
+        let region = format!("r.{}.{}", cx >> REGION_SHIFT, cz >> REGION_SHIFT);
+        self.dir.join(region).join(format!("c.{cx}.{cz}.bin"))
+    }
+
+    /// Returns `None` when the chunk was never cached. Blocks on disk I/O.
+    pub fn load(&self, cx: i32, cz: i32) -> Result<Option<Chunk>> {
+        let path = self.path(cx, cz);
+
+        if let Some(chunk) = self.pending_chunks().get(&path) {
+            return Ok(Some(Chunk::clone(chunk)));
+        }
+
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            bail!("Cached chunk ({cx}, {cz}) is corrupted");
+        }
+
+        if bytes[4] != VERSION {
+            bail!("Cached chunk ({cx}, {cz}) has unknown version {}", bytes[4]);
+        }
+
+        let has_sky_light = bytes[5] != 0;
+        let bit_map = u16::from_le_bytes([bytes[6], bytes[7]]);
+
+        let mut chunk = Chunk::new(cx, cz, has_sky_light);
+        chunk.load(bit_map, true, &bytes[HEADER_LEN..])?;
+
+        Ok(Some(chunk))
+    }
+
+    /// Loads the chunks on the blocking thread pool, missing and unreadable chunks are left out.
+    pub(crate) async fn load_many(&self, positions: Vec<(i32, i32)>) -> Vec<Chunk> {
+        let cache = self.clone();
+
+        let load = tokio::task::spawn_blocking(move || {
+            positions
+                .into_iter()
+                .filter_map(|(cx, cz)| match cache.load(cx, cz) {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        eprintln!("[ERROR] Failed to read cached chunk: {:?}", e);
+                        None
+                    }
+                })
+                .collect()
+        });
+
+        load.await.unwrap_or_default()
+    }
+
+    /// Writes the chunks on the blocking thread pool, `load` sees them right away.
+    pub(crate) fn save_in_background(&self, chunks: Vec<Chunk>) {
+        if chunks.is_empty() {
+            return;
+        }
+
+        let paths = self.queue(chunks);
+        let cache = self.clone();
+
+        let write = move || {
+            if let Err(e) = cache.write_pending(paths) {
+                eprintln!("[ERROR] Failed to cache chunk: {:?}", e);
+            }
+        };
+
+        // Worlds dropped outside of a runtime still get their chunks saved
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(write)),
+            Err(_) => write(),
+        }
+    }
+
+    /// Writes the chunks on the blocking thread pool and waits for them to be on disk.
+    pub(crate) async fn save_many(&self, chunks: Vec<Chunk>) -> Result<()> {
+        let paths = self.queue(chunks);
+        let cache = self.clone();
+
+        tokio::task::spawn_blocking(move || cache.write_pending(paths)).await?
+    }
+
+    fn queue(&self, chunks: Vec<Chunk>) -> Vec<PathBuf> {
+        let mut pending = self.pending_chunks();
+
+        chunks
+            .into_iter()
+            .map(|chunk| {
+                let path = self.path(chunk.x, chunk.z);
+                pending.insert(path.clone(), Arc::new(chunk));
+                path
+            })
+            .collect()
+    }
+
+    /// Writes every queued chunk even if some fail, returning the last error.
+    fn write_pending(&self, paths: Vec<PathBuf>) -> Result<()> {
+        let _writing = self
+            .pending
+            .writing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut result = Ok(());
+
+        for path in paths {
+            // The latest copy, which may be newer than the one this write was queued for
+            let Some(chunk) = self.pending_chunks().get(&path).cloned() else {
+                continue;
+            };
+
+            if let Err(e) = self.save(&chunk) {
+                result = Err(e);
+            }
+
+            let mut pending = self.pending_chunks();
+
+            if pending
+                .get(&path)
+                .is_some_and(|queued| Arc::ptr_eq(queued, &chunk))
+            {
+                pending.remove(&path);
+            }
+        }
+
+        result
+    }
+
+    fn pending_chunks(&self) -> MutexGuard<'_, HashMap<PathBuf, Arc<Chunk>>> {
+        self.pending
+            .chunks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Blocks on disk I/O, see `World::save_cache` to write from a bot.
+    pub fn save(&self, chunk: &Chunk) -> Result<()> {
+        let path = self.path(chunk.x, chunk.z);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let data = chunk.encode();
+        let mut bytes = Vec::with_capacity(HEADER_LEN + data.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(chunk.has_sky_light() as u8);
+        bytes.extend_from_slice(&chunk.bit_map().to_le_bytes());
+        bytes.extend_from_slice(&data);
+
+        // Write then rename so a crash never leaves a half written chunk behind
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::chunk::BlockState;
+    use crate::World;
+
+    fn chunk(cx: i32, cz: i32) -> Chunk {
+        let mut chunk = Chunk::new(cx, cz, true);
+        chunk.set_block(1, 64, 2, BlockState::new(1, 0));
+        chunk.set_block(15, 200, 15, BlockState::new(44, 8));
+        chunk
+    }
+
+    fn scoped(dir: &TempDir) -> WorldCache {
+        WorldCache::new(dir.path()).scoped("mc.example.com:25565", Dimension::Overworld)
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new().unwrap();
+        scoped(&dir).save(&chunk(33, -1)).unwrap();
+
+        let path = dir
+            .path()
+            .join("mc.example.com_25565")
+            .join("overworld")
+            .join("r.1.-1")
+            .join("c.33.-1.bin");
+        assert!(path.is_file());
+        assert!(!path.with_extension("tmp").exists());
+
+        // A new cache, so nothing comes from the pending writes
+        let loaded = scoped(&dir).load(33, -1).unwrap().unwrap();
+
+        assert_eq!((loaded.x, loaded.z), (33, -1));
+        assert!(loaded.has_sky_light());
+        assert_eq!(loaded.bit_map(), chunk(33, -1).bit_map());
+        assert_eq!(loaded.encode(), chunk(33, -1).encode());
+
+        assert!(scoped(&dir).load(0, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn ignores_leftover_tmp_files() {
+        let dir = TempDir::new().unwrap();
+        let cache = scoped(&dir);
+        let path = cache.path(0, 0);
+
+        // What a crash in the middle of a write leaves behind
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path.with_extension("tmp"), b"OGCC\x01").unwrap();
+
+        assert!(cache.load(0, 0).unwrap().is_none());
+
+        cache.save(&chunk(0, 0)).unwrap();
+        assert_eq!(
+            cache.load(0, 0).unwrap().unwrap().encode(),
+            chunk(0, 0).encode()
+        );
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn rejects_corrupted_chunks() {
+        let dir = TempDir::new().unwrap();
+        let cache = scoped(&dir);
+        let path = cache.path(0, 0);
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"not a chunk").unwrap();
+        assert!(cache.load(0, 0).is_err());
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[VERSION + 1, 1, 0, 0]);
+        fs::write(&path, bytes).unwrap();
+        assert!(cache.load(0, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn pending_writes_are_read_back() {
+        let dir = TempDir::new().unwrap();
+        let cache = scoped(&dir);
+
+        cache.save_in_background(vec![chunk(2, 3)]);
+        assert!(cache.load(2, 3).unwrap().is_some());
+
+        cache.save_many(vec![chunk(4, 5)]).await.unwrap();
+        assert!(cache.path(4, 5).is_file());
+        assert!(scoped(&dir).load(4, 5).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn world_saves_changed_chunks() {
+        let dir = TempDir::new().unwrap();
+
+        let mut world = World::empty(0);
+        world.set_cache(Some(scoped(&dir)));
+        world.set_block(3, 70, 3, BlockState::new(1, 0));
+        world.save_cache().await.unwrap();
+
+        let loaded = scoped(&dir).load(0, 0).unwrap().unwrap();
+        assert_eq!(loaded.block(3, 70, 3), BlockState::new(1, 0));
+
+        // Saved chunks aren't dirty anymore, removing the file shows nothing is rewritten
+        fs::remove_file(scoped(&dir).path(0, 0)).unwrap();
+        world.save_cache().await.unwrap();
+        assert!(!scoped(&dir).path(0, 0).exists());
+    }
+}
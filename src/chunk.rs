@@ -0,0 +1,442 @@
+use anyhow::{bail, Result};
+
+pub const SECTION_COUNT: usize = 16;
+pub const SECTION_VOLUME: usize = 16 * 16 * 16;
+
+const BLOCKS_LEN: usize = SECTION_VOLUME * 2;
+const LIGHT_LEN: usize = SECTION_VOLUME / 2;
+const BIOMES_LEN: usize = 16 * 16;
+
+/// A 1.8 block state, packed as `id << 4 | metadata`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockState(u16);
+
+impl BlockState {
+    pub const AIR: Self = Self(0);
+
+    pub fn new(id: u16, metadata: u8) -> Self {
+        Self(id << 4 | (metadata & 0x0F) as u16)
+    }
+
+    pub fn from_raw(raw: u16) -> Self {
+        Self(raw)
+    }
+
+    pub fn raw(&self) -> u16 {
+        self.0
+    }
+
+    pub fn id(&self) -> u16 {
+        self.0 >> 4
+    }
+
+    pub fn metadata(&self) -> u8 {
+        (self.0 & 0x0F) as u8
+    }
+
+    pub fn is_air(&self) -> bool {
+        self.id() == 0
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32, dz: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkSection {
+    blocks: Box<[u16; SECTION_VOLUME]>,
+    block_light: Box<[u8; LIGHT_LEN]>,
+    sky_light: Option<Box<[u8; LIGHT_LEN]>>,
+}
+
+impl ChunkSection {
+    pub fn new(has_sky_light: bool) -> Self {
+        Self {
+            blocks: Box::new([0; SECTION_VOLUME]),
+            block_light: Box::new([0; LIGHT_LEN]),
+            sky_light: has_sky_light.then(|| Box::new([0xFF; LIGHT_LEN])),
+        }
+    }
+
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        y << 8 | z << 4 | x
+    }
+
+    pub fn block(&self, x: usize, y: usize, z: usize) -> BlockState {
+        BlockState(self.blocks[Self::index(x, y, z)])
+    }
+
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: BlockState) {
+        self.blocks[Self::index(x, y, z)] = state.0;
+    }
+
+    pub fn block_light(&self, x: usize, y: usize, z: usize) -> u8 {
+        nibble(&self.block_light[..], Self::index(x, y, z))
+    }
+
+    pub fn sky_light(&self, x: usize, y: usize, z: usize) -> Option<u8> {
+        let sky_light = self.sky_light.as_ref()?;
+        Some(nibble(&sky_light[..], Self::index(x, y, z)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|&block| block == 0)
+    }
+}
+
+fn nibble(data: &[u8], index: usize) -> u8 {
+    match index & 1 {
+        0 => data[index >> 1] & 0x0F,
+        _ => data[index >> 1] >> 4,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub x: i32,
+    pub z: i32,
+    sections: [Option<ChunkSection>; SECTION_COUNT],
+    biomes: Box<[u8; BIOMES_LEN]>,
+    has_sky_light: bool,
+}
+
+impl Chunk {
+    pub fn new(x: i32, z: i32, has_sky_light: bool) -> Self {
+        Self {
+            x,
+            z,
+            sections: Default::default(),
+            biomes: Box::new([0; BIOMES_LEN]),
+            has_sky_light,
+        }
+    }
+
+    pub fn section(&self, index: usize) -> Option<&ChunkSection> {
+        self.sections.get(index)?.as_ref()
+    }
+
+    pub fn sections(&self) -> impl Iterator<Item = (usize, &ChunkSection)> {
+        self.sections
+            .iter()
+            .enumerate()
+            .filter_map(|(i, section)| Some((i, section.as_ref()?)))
+    }
+
+    /// Coordinates are local to the chunk: `x` and `z` in `0..16`, `y` in `0..256`.
+    pub fn block(&self, x: usize, y: usize, z: usize) -> BlockState {
+        match self.section(y >> 4) {
+            Some(section) => section.block(x, y & 0x0F, z),
+            None => BlockState::AIR,
+        }
+    }
+
+    /// Returns the block state that was replaced.
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: BlockState) -> BlockState {
+        let Some(slot) = self.sections.get_mut(y >> 4) else {
+            return BlockState::AIR;
+        };
+
+        if slot.is_none() && state.is_air() {
+            return BlockState::AIR;
+        }
+
+        let has_sky_light = self.has_sky_light;
+        let section = slot.get_or_insert_with(|| ChunkSection::new(has_sky_light));
+        let old = section.block(x, y & 0x0F, z);
+        section.set_block(x, y & 0x0F, z, state);
+
+        old
+    }
+
+    pub fn biome(&self, x: usize, z: usize) -> u8 {
+        self.biomes[z << 4 | x]
+    }
+
+    /// Decodes 1.8 chunk data into this chunk and returns the amount of bytes consumed.
+    ///
+    /// The layout is every section's blocks, then every section's block light, then every
+    /// section's sky light (if sent) and finally the biomes when `ground_up` is set.
+    pub fn load(&mut self, bit_map: u16, ground_up: bool, data: &[u8]) -> Result<usize> {
+        let count = bit_map.count_ones() as usize;
+        let expected = Self::data_len(bit_map, ground_up, self.has_sky_light);
+
+        if data.len() < expected {
+            bail!(
+                "Chunk data for ({}, {}) is too short: expected {expected} bytes, got {}",
+                self.x,
+                self.z,
+                data.len()
+            );
+        }
+
+        let (blocks, rest) = data.split_at(count * BLOCKS_LEN);
+        let (block_light, rest) = rest.split_at(count * LIGHT_LEN);
+        let (sky_light, rest) = match self.has_sky_light {
+            true => rest.split_at(count * LIGHT_LEN),
+            false => (&rest[..0], rest),
+        };
+
+        let has_sky_light = self.has_sky_light;
+        let indexes = (0..SECTION_COUNT).filter(|i| bit_map & (1 << i) != 0);
+
+        for (n, i) in indexes.enumerate() {
+            let mut section = ChunkSection::new(has_sky_light);
+
+            let raw = &blocks[n * BLOCKS_LEN..(n + 1) * BLOCKS_LEN];
+            for (block, bytes) in section.blocks.iter_mut().zip(raw.chunks_exact(2)) {
+                *block = u16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+
+            let raw = &block_light[n * LIGHT_LEN..(n + 1) * LIGHT_LEN];
+            section.block_light.copy_from_slice(raw);
+
+            if let Some(ref mut light) = section.sky_light {
+                light.copy_from_slice(&sky_light[n * LIGHT_LEN..(n + 1) * LIGHT_LEN]);
+            }
+
+            self.sections[i] = Some(section);
+        }
+
+        if ground_up {
+            // A ground-up chunk replaces every section, so the missing ones are empty
+            for (i, section) in self.sections.iter_mut().enumerate() {
+                if bit_map & (1 << i) == 0 {
+                    *section = None;
+                }
+            }
+
+            self.biomes.copy_from_slice(&rest[..BIOMES_LEN]);
+        }
+
+        Ok(expected)
+    }
+
+    pub fn has_sky_light(&self) -> bool {
+        self.has_sky_light
+    }
+
+    /// The mask of the sections that are present, as sent by the server.
+    pub fn bit_map(&self) -> u16 {
+        self.sections
+            .iter()
+            .enumerate()
+            .filter(|(_, section)| section.is_some())
+            .fold(0, |bit_map, (i, _)| bit_map | 1 << i)
+    }
+
+    /// Encodes the chunk back into the ground-up format read by `load`.
+    pub fn encode(&self) -> Vec<u8> {
+        let bit_map = self.bit_map();
+        let mut data = Vec::with_capacity(Self::data_len(bit_map, true, self.has_sky_light));
+        let sections = self.sections.iter().flatten();
+
+        for section in sections.clone() {
+            data.extend(section.blocks.iter().flat_map(|block| block.to_le_bytes()));
+        }
+
+        for section in sections.clone() {
+            data.extend_from_slice(&section.block_light[..]);
+        }
+
+        if self.has_sky_light {
+            for section in sections {
+                match section.sky_light {
+                    Some(ref light) => data.extend_from_slice(&light[..]),
+                    None => data.extend_from_slice(&[0xFF; LIGHT_LEN]),
+                }
+            }
+        }
+
+        data.extend_from_slice(&self.biomes[..]);
+
+        data
+    }
+
+    pub fn data_len(bit_map: u16, ground_up: bool, has_sky_light: bool) -> usize {
+        let count = bit_map.count_ones() as usize;
+        let light = match has_sky_light {
+            true => LIGHT_LEN * 2,
+            false => LIGHT_LEN,
+        };
+
+        let biomes = match ground_up {
+            true => BIOMES_LEN,
+            false => 0,
+        };
+
+        count * (BLOCKS_LEN + light) + biomes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: BlockState = BlockState(1 << 4);
+
+    /// Section data with every block set to `state`, then uniform light.
+    fn section_data(count: usize, state: BlockState, has_sky_light: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        for _ in 0..count {
+            data.extend((0..SECTION_VOLUME).flat_map(|_| state.0.to_le_bytes()));
+        }
+
+        data.extend(std::iter::repeat(0x11).take(count * LIGHT_LEN));
+
+        if has_sky_light {
+            data.extend(std::iter::repeat(0xEE).take(count * LIGHT_LEN));
+        }
+
+        data
+    }
+
+    #[test]
+    fn loads_a_single_section() {
+        let mut data = section_data(1, STONE, true);
+        data.extend([7; BIOMES_LEN]);
+
+        let mut chunk = Chunk::new(2, -3, true);
+        let read = chunk.load(1 << 4, true, &data).unwrap();
+
+        assert_eq!(read, data.len());
+        assert_eq!(chunk.bit_map(), 1 << 4);
+        assert_eq!(chunk.block(0, 64, 0), STONE);
+        assert_eq!(chunk.block(15, 79, 15), STONE);
+        assert_eq!(chunk.block(0, 63, 0), BlockState::AIR);
+        assert_eq!(chunk.block(0, 80, 0), BlockState::AIR);
+
+        let section = chunk.section(4).unwrap();
+        assert_eq!(section.block_light(3, 2, 1), 1);
+        assert_eq!(section.sky_light(3, 2, 1), Some(14));
+        assert_eq!(chunk.biome(5, 5), 7);
+    }
+
+    #[test]
+    fn loads_full_and_partial_bit_maps() {
+        let mut data = section_data(16, STONE, false);
+        data.extend([0; BIOMES_LEN]);
+
+        let mut chunk = Chunk::new(0, 0, false);
+        chunk.load(0xFFFF, true, &data).unwrap();
+
+        assert_eq!(chunk.sections().count(), 16);
+        assert_eq!(chunk.block(8, 255, 8), STONE);
+
+        // Sections 0 and 2, the second one is made of glass
+        let mut data = section_data(1, STONE, false);
+        let glass = section_data(1, BlockState::new(20, 0), false);
+        data.truncate(BLOCKS_LEN);
+        data.extend_from_slice(&glass[..BLOCKS_LEN]);
+        data.extend(std::iter::repeat(0).take(2 * LIGHT_LEN));
+        data.extend([0; BIOMES_LEN]);
+
+        let mut chunk = Chunk::new(0, 0, false);
+        chunk.load(0b101, true, &data).unwrap();
+
+        assert_eq!(chunk.bit_map(), 0b101);
+        assert_eq!(chunk.block(0, 0, 0), STONE);
+        assert_eq!(chunk.block(0, 16, 0), BlockState::AIR);
+        assert_eq!(chunk.block(0, 32, 0).id(), 20);
+    }
+
+    #[test]
+    fn ground_up_replaces_every_section() {
+        let mut data = section_data(2, STONE, true);
+        data.extend([0; BIOMES_LEN]);
+
+        let mut chunk = Chunk::new(0, 0, true);
+        chunk.load(0b11, true, &data).unwrap();
+
+        // Not ground-up: only the sent section changes, and there are no biomes
+        let update = section_data(1, BlockState::new(3, 0), true);
+        let read = chunk.load(0b10, false, &update).unwrap();
+
+        assert_eq!(read, update.len());
+        assert_eq!(chunk.block(0, 0, 0), STONE);
+        assert_eq!(chunk.block(0, 16, 0).id(), 3);
+
+        // Ground-up: the sections that weren't sent are cleared
+        let mut data = section_data(1, STONE, true);
+        data.extend([0; BIOMES_LEN]);
+        chunk.load(0b100, true, &data).unwrap();
+
+        assert_eq!(chunk.bit_map(), 0b100);
+        assert_eq!(chunk.block(0, 0, 0), BlockState::AIR);
+    }
+
+    #[test]
+    fn loads_without_sky_light() {
+        let mut data = section_data(1, STONE, false);
+        data.extend([0; BIOMES_LEN]);
+
+        assert_eq!(data.len(), Chunk::data_len(1, true, false));
+
+        let mut chunk = Chunk::new(0, 0, false);
+        chunk.load(1, true, &data).unwrap();
+
+        assert_eq!(chunk.section(0).unwrap().sky_light(0, 0, 0), None);
+        assert_eq!(chunk.block(1, 1, 1), STONE);
+
+        // The same data read as if it had sky light is too short
+        let mut chunk = Chunk::new(0, 0, true);
+        assert!(chunk.load(1, true, &data).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let mut data = section_data(2, STONE, true);
+        data.extend([0; BIOMES_LEN]);
+        data.pop();
+
+        let mut chunk = Chunk::new(0, 0, true);
+        chunk.set_block(0, 0, 0, STONE);
+
+        assert!(chunk.load(0b11, true, &data).is_err());
+        // Nothing is decoded from a short packet
+        assert_eq!(chunk.bit_map(), 1);
+        assert_eq!(chunk.block(0, 16, 0), BlockState::AIR);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for has_sky_light in [true, false] {
+            let mut chunk = Chunk::new(4, 5, has_sky_light);
+            chunk.set_block(0, 0, 0, STONE);
+            chunk.set_block(15, 100, 3, BlockState::new(44, 8));
+            chunk.set_block(7, 255, 7, BlockState::new(20, 0));
+
+            let data = chunk.encode();
+            assert_eq!(
+                data.len(),
+                Chunk::data_len(chunk.bit_map(), true, has_sky_light)
+            );
+
+            let mut loaded = Chunk::new(4, 5, has_sky_light);
+            assert_eq!(
+                loaded.load(chunk.bit_map(), true, &data).unwrap(),
+                data.len()
+            );
+
+            assert_eq!(loaded.bit_map(), chunk.bit_map());
+            assert_eq!(loaded.encode(), data);
+            assert_eq!(loaded.block(15, 100, 3), BlockState::new(44, 8));
+            assert_eq!(loaded.block(7, 255, 7).id(), 20);
+            assert_eq!(loaded.block(1, 0, 0), BlockState::AIR);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use aes::cipher::inout::InOutBuf;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::Aes128;
+use anyhow::Result;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+use sha1::{Digest, Sha1};
+
+pub type Encryptor = cfb8::Encryptor<Aes128>;
+pub type Decryptor = cfb8::Decryptor<Aes128>;
+
+pub type SharedSecret = [u8; 16];
+
+pub fn generate_shared_secret() -> SharedSecret {
+    rand::random()
+}
+
+/// The shared secret is used as both the AES key and IV.
+pub fn ciphers(secret: &SharedSecret) -> (Encryptor, Decryptor) {
+    let encryptor = Encryptor::new(secret.into(), secret.into());
+    let decryptor = Decryptor::new(secret.into(), secret.into());
+    (encryptor, decryptor)
+}
+
+// CFB8 works on single byte blocks so there is never a remainder
+pub fn encrypt(cipher: &mut Encryptor, data: &mut [u8]) {
+    let (blocks, _) = InOutBuf::from(data).into_chunks();
+    cipher.encrypt_blocks_inout_mut(blocks);
+}
+
+pub fn decrypt(cipher: &mut Decryptor, data: &mut [u8]) {
+    let (blocks, _) = InOutBuf::from(data).into_chunks();
+    cipher.decrypt_blocks_inout_mut(blocks);
+}
+
+/// Encrypts `data` with the server's DER encoded RSA public key.
+pub fn encrypt_with_public_key(public_key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let key = RsaPublicKey::from_public_key_der(public_key)?;
+    let encrypted = key.encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, data)?;
+    Ok(encrypted)
+}
+
+/// Minecraft's server hash: a SHA-1 digest printed as a signed big integer in hex.
+pub fn server_hash(server_id: &str, secret: &SharedSecret, public_key: &[u8]) -> String {
+    let digest = Sha1::new()
+        .chain_update(server_id.as_bytes())
+        .chain_update(secret)
+        .chain_update(public_key)
+        .finalize()
+        .into();
+
+    signed_hex(digest)
+}
+
+fn signed_hex(mut digest: [u8; 20]) -> String {
+    let negative = digest[0] & 0x80 != 0;
+
+    if negative {
+        // Two's complement to get the absolute value
+        let mut carry = true;
+
+        for byte in digest.iter_mut().rev() {
+            *byte = !*byte;
+
+            if carry {
+                let (value, overflow) = byte.overflowing_add(1);
+                *byte = value;
+                carry = overflow;
+            }
+        }
+    }
+
+    let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+    let hex = hex.trim_start_matches('0');
+
+    match negative {
+        true => format!("-{hex}"),
+        false => hex.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_hex_digests() {
+        // Known values of Minecraft's hash for a single string
+        let cases = [
+            ("Notch", "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"),
+            ("jeb_", "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"),
+            ("simon", "88e16a1019277b15d58faf0541e11910eb756f6"),
+        ];
+
+        for (input, expected) in cases {
+            let digest = Sha1::digest(input.as_bytes()).into();
+            assert_eq!(signed_hex(digest), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn cfb8_round_trip() {
+        let secret = generate_shared_secret();
+        let (mut encryptor, _) = ciphers(&secret);
+        let (_, mut decryptor) = ciphers(&secret);
+
+        let plain: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut data = plain.clone();
+
+        // The stream state carries over between calls, like packets on a connection
+        for chunk in data.chunks_mut(97) {
+            encrypt(&mut encryptor, chunk);
+        }
+
+        assert_ne!(data, plain);
+
+        for chunk in data.chunks_mut(13) {
+            decrypt(&mut decryptor, chunk);
+        }
+
+        assert_eq!(data, plain);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::math::Vec3;
+
+/// Positions are sent as fixed-point integers with 5 fractional bits.
+const POSITION_SCALE: f64 = 32.0;
+/// Velocities are sent in 1/8000 of a block per tick.
+const VELOCITY_SCALE: f64 = 8000.0;
+
+pub fn from_fixed(value: i32) -> f64 {
+    value as f64 / POSITION_SCALE
+}
+
+/// Angles are sent as a single byte where 256 steps make a full turn.
+pub fn from_angle(value: i8) -> f32 {
+    value as f32 * 360.0 / 256.0
+}
+
+pub fn from_velocity(value: i16) -> f64 {
+    value as f64 / VELOCITY_SCALE
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub head_yaw: f32,
+    pub on_ground: bool,
+    // The client keeps the raw fixed-point position so relative moves never accumulate
+    // rounding errors, the same way vanilla's `serverPosX/Y/Z` does.
+    fixed: (i32, i32, i32),
+}
+
+impl Transform {
+    pub fn new(x: i32, y: i32, z: i32, yaw: i8, pitch: i8) -> Self {
+        let mut transform = Self::default();
+        transform.teleport(x, y, z);
+        transform.rotate(yaw, pitch);
+        transform.head_yaw = transform.yaw;
+        transform
+    }
+
+    pub fn teleport(&mut self, x: i32, y: i32, z: i32) {
+        self.fixed = (x, y, z);
+        self.sync_position();
+    }
+
+    pub fn translate(&mut self, dx: i8, dy: i8, dz: i8) {
+        self.fixed.0 += dx as i32;
+        self.fixed.1 += dy as i32;
+        self.fixed.2 += dz as i32;
+        self.sync_position();
+    }
+
+    pub fn rotate(&mut self, yaw: i8, pitch: i8) {
+        self.yaw = from_angle(yaw);
+        self.pitch = from_angle(pitch);
+    }
+
+    pub fn set_velocity(&mut self, x: i16, y: i16, z: i16) {
+        self.velocity = Vec3::new(from_velocity(x), from_velocity(y), from_velocity(z));
+    }
+
+    fn sync_position(&mut self) {
+        let (x, y, z) = self.fixed;
+        self.position = Vec3::new(from_fixed(x), from_fixed(y), from_fixed(z));
+    }
+}
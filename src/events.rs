@@ -1,764 +1,496 @@
-use crate::packets::{play, Packets, ServerPacket};
-use crate::Bot;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::future::Future;
+use std::rc::Rc;
 
-pub struct Context<'bot, 'payload, T> {
-    pub bot: &'bot Bot<'bot>,
-    pub payload: &'payload T,
-}
-
-pub type EventHandler<T> = Box<dyn Fn(&Context<T>)>;
-
-#[derive(Default)]
-pub struct EventHandlers {
-    pub tick_handlers: Vec<EventHandler<()>>,
-    pub on_connect_handlers: Vec<EventHandler<()>>,
-    keep_alive_handlers: Vec<EventHandler<play::server::KeepAlive>>,
-    join_game_handlers: Vec<EventHandler<play::server::JoinGame>>,
-    chat_handlers: Vec<EventHandler<play::server::Chat>>,
-    update_time_handlers: Vec<EventHandler<play::server::UpdateTime>>,
-    spawn_position_handlers: Vec<EventHandler<play::server::SpawnPosition>>,
-    update_health_handlers: Vec<EventHandler<play::server::UpdateHealth>>,
-    respawn_handlers: Vec<EventHandler<play::server::Respawn>>,
-    position_handlers: Vec<EventHandler<play::server::Position>>,
-    held_item_slot_handlers: Vec<EventHandler<play::server::HeldItemSlot>>,
-    bed_handlers: Vec<EventHandler<play::server::Bed>>,
-    animation_handlers: Vec<EventHandler<play::server::Animation>>,
-    collect_handlers: Vec<EventHandler<play::server::Collect>>,
-    spawn_player_handlers: Vec<EventHandler<play::server::SpawnPlayer>>,
-    spawn_mob_handlers: Vec<EventHandler<play::server::SpawnMob>>,
-    spawn_entity_painting_handlers: Vec<EventHandler<play::server::SpawnEntityPainting>>,
-    spawn_entity_experience_orb_handlers: Vec<EventHandler<play::server::SpawnEntityExperienceOrb>>,
-    entity_velocity_handlers: Vec<EventHandler<play::server::EntityVelocity>>,
-    entity_destroy_handlers: Vec<EventHandler<play::server::EntityDestroy>>,
-    entity_handlers: Vec<EventHandler<play::server::Entity>>,
-    entity_relative_move_handlers: Vec<EventHandler<play::server::EntityRelativeMove>>,
-    entity_look_handlers: Vec<EventHandler<play::server::EntityLook>>,
-    entity_move_look_handlers: Vec<EventHandler<play::server::EntityMoveLook>>,
-    entity_teleport_handlers: Vec<EventHandler<play::server::EntityTeleport>>,
-    entity_head_rotation_handlers: Vec<EventHandler<play::server::EntityHeadRotation>>,
-    entity_status_handlers: Vec<EventHandler<play::server::EntityStatus>>,
-    attach_entity_handlers: Vec<EventHandler<play::server::AttachEntity>>,
-    entity_metadata_handlers: Vec<EventHandler<play::server::EntityMetadata>>,
-    entity_effect_handlers: Vec<EventHandler<play::server::EntityEffect>>,
-    remove_entity_effect_handlers: Vec<EventHandler<play::server::RemoveEntityEffect>>,
-    block_change_handlers: Vec<EventHandler<play::server::BlockChange>>,
-    kick_disconnect_handlers: Vec<EventHandler<play::server::KickDisconnect>>,
-    server_difficulty_handlers: Vec<EventHandler<play::server::ServerDifficulty>>,
-    scoreboard_objective_handlers: Vec<EventHandler<play::server::ScoreboardObjective>>,
-    scoreboard_update_handlers: Vec<EventHandler<play::server::ScoreboardUpdate>>,
-    scoreboard_display_handlers: Vec<EventHandler<play::server::ScoreboardDisplay>>,
-    teams_handlers: Vec<EventHandler<play::server::Teams>>,
-    set_slot_handlers: Vec<EventHandler<play::server::SetSlot>>,
-    entity_equipment_handlers: Vec<EventHandler<play::server::EntityEquipment>>,
-}
-
-impl EventHandlers {
-    pub fn dispatch<'bot, 'packet, T>(&self, payload: &'packet T, bot: &'bot Bot)
-    where
-        Context<'bot, 'packet, T>: 'packet + Dispatchable,
-    {
-        let ctx = Context { bot, payload };
-        ctx.dispatch_packet_event(bot);
-    }
-}
+use futures::future::LocalBoxFuture;
 
-pub trait PacketHandler<T: ServerPacket> {
-    fn register(self, events: &mut EventHandlers);
-}
-
-impl<F> PacketHandler<play::server::KeepAlive> for F
-where
-    F: Fn(&Context<play::server::KeepAlive>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.keep_alive_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::JoinGame> for F
-where
-    F: Fn(&Context<play::server::JoinGame>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.join_game_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::Chat> for F
-where
-    F: Fn(&Context<play::server::Chat>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.chat_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::UpdateTime> for F
-where
-    F: Fn(&Context<play::server::UpdateTime>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.update_time_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::SpawnPosition> for F
-where
-    F: Fn(&Context<play::server::SpawnPosition>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.spawn_position_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::UpdateHealth> for F
-where
-    F: Fn(&Context<play::server::UpdateHealth>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.update_health_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::Respawn> for F
-where
-    F: Fn(&Context<play::server::Respawn>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.respawn_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::Position> for F
-where
-    F: Fn(&Context<play::server::Position>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.position_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::HeldItemSlot> for F
-where
-    F: Fn(&Context<play::server::HeldItemSlot>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.held_item_slot_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::Bed> for F
-where
-    F: Fn(&Context<play::server::Bed>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.bed_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::Animation> for F
-where
-    F: Fn(&Context<play::server::Animation>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.animation_handlers.push(Box::new(self));
-    }
-}
+use crate::handle::BotHandle;
+use crate::packets::{play, Packets, ServerPacket};
+use crate::policy::{Reconnected, Reconnecting};
+use crate::semantic::{
+    Death, DimensionChange, EntityDespawn, EntityMove, EntitySpawn, HealthChange, Hurt, ItemPickup,
+    PlayerJoin, PlayerLeave, Spawn,
+};
+use crate::world::BlockUpdate;
+use crate::Bot;
 
-impl<F> PacketHandler<play::server::Collect> for F
-where
-    F: Fn(&Context<play::server::Collect>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.collect_handlers.push(Box::new(self));
-    }
+/// Handlers get exclusive access to the bot, so they can change its state and send packets.
+pub struct Context<'a, T, S = ()> {
+    pub bot: &'a mut Bot,
+    /// The state given to `BotBuilder::with_state`, kept across reconnects.
+    pub state: &'a mut S,
+    pub payload: &'a T,
+    cancel: Cancel,
 }
 
-impl<F> PacketHandler<play::server::SpawnPlayer> for F
-where
-    F: Fn(&Context<play::server::SpawnPlayer>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.spawn_player_handlers.push(Box::new(self));
+impl<T, S> Context<'_, T, S> {
+    /// Stops lower-priority handlers of this event from running.
+    pub fn cancel(&mut self) {
+        self.cancel = self.cancel.max(Cancel::Handlers);
     }
-}
 
-impl<F> PacketHandler<play::server::SpawnMob> for F
-where
-    F: Fn(&Context<play::server::SpawnMob>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.spawn_mob_handlers.push(Box::new(self));
+    /// Also skips the bot's own handling of the packet, e.g. the inventory update of a
+    /// `SetSlot`. The bot's state can then differ from the server's.
+    pub fn cancel_builtin(&mut self) {
+        self.cancel = Cancel::Builtin;
     }
-}
 
-impl<F> PacketHandler<play::server::SpawnEntityPainting> for F
-where
-    F: Fn(&Context<play::server::SpawnEntityPainting>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.spawn_entity_painting_handlers.push(Box::new(self));
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel != Cancel::None
     }
 }
 
-impl<F> PacketHandler<play::server::SpawnEntityExperienceOrb> for F
-where
-    F: Fn(&Context<play::server::SpawnEntityExperienceOrb>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events
-            .spawn_entity_experience_orb_handlers
-            .push(Box::new(self));
-    }
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cancel {
+    #[default]
+    None,
+    Handlers,
+    Builtin,
 }
 
-impl<F> PacketHandler<play::server::EntityVelocity> for F
-where
-    F: Fn(&Context<play::server::EntityVelocity>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_velocity_handlers.push(Box::new(self));
-    }
+/// Passed to async handlers, which can outlive the packet that triggered them. The bot's
+/// state isn't available, see `BotBuilder::with_state`.
+pub struct AsyncContext<T> {
+    pub bot: BotHandle,
+    pub payload: T,
 }
 
-impl<F> PacketHandler<play::server::EntityDestroy> for F
-where
-    F: Fn(&Context<play::server::EntityDestroy>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_destroy_handlers.push(Box::new(self));
-    }
+pub enum EventHandler<T> {
+    Sync(Box<dyn Fn(&mut Bot, &mut dyn Any, &T) -> Cancel>),
+    /// Started when the event is dispatched, then polled by the bot's run loop alongside
+    /// the tick interval and incoming packets. A task is never polled while a packet batch
+    /// or a tick is being handled, so it sees the bot as the last one left it. Async handlers
+    /// run too late to cancel anything. A lost connection doesn't cancel them, they're only
+    /// dropped once the bot stops for good.
+    Async(Box<dyn Fn(AsyncContext<T>) -> LocalBoxFuture<'static, ()>>),
 }
 
-impl<F> PacketHandler<play::server::Entity> for F
-where
-    F: Fn(&Context<play::server::Entity>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_handlers.push(Box::new(self));
-    }
+/// Selects how a closure is registered, lets `on_*` methods take sync and async handlers.
+pub trait IntoEventHandler<T, S, Marker> {
+    fn into_handler(self) -> EventHandler<T>;
 }
 
-impl<F> PacketHandler<play::server::EntityRelativeMove> for F
-where
-    F: Fn(&Context<play::server::EntityRelativeMove>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_relative_move_handlers.push(Box::new(self));
-    }
-}
+pub struct SyncMarker;
+pub struct AsyncMarker;
 
-impl<F> PacketHandler<play::server::EntityLook> for F
+impl<T, S: 'static, F> IntoEventHandler<T, S, SyncMarker> for F
 where
-    F: Fn(&Context<play::server::EntityLook>) + 'static,
+    F: Fn(&mut Context<T, S>) + 'static,
 {
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_look_handlers.push(Box::new(self));
-    }
-}
+    fn into_handler(self) -> EventHandler<T> {
+        EventHandler::Sync(Box::new(move |bot, state, payload| {
+            // Handlers are only registered through a `BotBuilder<S>`, so this always succeeds
+            let Some(state) = state.downcast_mut::<S>() else {
+                return Cancel::None;
+            };
 
-impl<F> PacketHandler<play::server::EntityMoveLook> for F
-where
-    F: Fn(&Context<play::server::EntityMoveLook>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_move_look_handlers.push(Box::new(self));
-    }
-}
+            let mut ctx = Context {
+                bot,
+                state,
+                payload,
+                cancel: Cancel::None,
+            };
 
-impl<F> PacketHandler<play::server::EntityTeleport> for F
-where
-    F: Fn(&Context<play::server::EntityTeleport>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_teleport_handlers.push(Box::new(self));
+            self(&mut ctx);
+            ctx.cancel
+        }))
     }
 }
 
-impl<F> PacketHandler<play::server::EntityHeadRotation> for F
+impl<T, S, F, Fut> IntoEventHandler<T, S, AsyncMarker> for F
 where
-    F: Fn(&Context<play::server::EntityHeadRotation>) + 'static,
+    F: Fn(AsyncContext<T>) -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
 {
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_head_rotation_handlers.push(Box::new(self));
+    fn into_handler(self) -> EventHandler<T> {
+        EventHandler::Async(Box::new(move |ctx| Box::pin(self(ctx))))
     }
 }
 
-impl<F> PacketHandler<play::server::EntityStatus> for F
-where
-    F: Fn(&Context<play::server::EntityStatus>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_status_handlers.push(Box::new(self));
-    }
+struct HandlerOptions {
+    priority: Cell<i32>,
+    once: Cell<bool>,
+    active: Cell<bool>,
 }
 
-impl<F> PacketHandler<play::server::AttachEntity> for F
-where
-    F: Fn(&Context<play::server::AttachEntity>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.attach_entity_handlers.push(Box::new(self));
-    }
+/// Returned by every `on_*` method to configure or remove the handler it registered.
+#[derive(Clone)]
+pub struct HandlerHandle {
+    options: Rc<HandlerOptions>,
 }
 
-impl<F> PacketHandler<play::server::EntityMetadata> for F
-where
-    F: Fn(&Context<play::server::EntityMetadata>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_metadata_handlers.push(Box::new(self));
+impl HandlerHandle {
+    /// Handlers with a higher priority run first, ties run in registration order. Can be
+    /// changed at any time, it applies from the next dispatch. Defaults to 0.
+    pub fn with_priority(self, priority: i32) -> Self {
+        self.options.priority.set(priority);
+        self
     }
-}
 
-impl<F> PacketHandler<play::server::EntityEffect> for F
-where
-    F: Fn(&Context<play::server::EntityEffect>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_effect_handlers.push(Box::new(self));
+    /// Unregisters the handler after it runs once. Skipped dispatches, like sync handlers
+    /// in a nested dispatch, don't count.
+    pub fn once(self) -> Self {
+        self.options.once.set(true);
+        self
     }
-}
 
-impl<F> PacketHandler<play::server::RemoveEntityEffect> for F
-where
-    F: Fn(&Context<play::server::RemoveEntityEffect>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.remove_entity_effect_handlers.push(Box::new(self));
+    pub fn unregister(&self) {
+        self.options.active.set(false);
     }
-}
 
-impl<F> PacketHandler<play::server::BlockChange> for F
-where
-    F: Fn(&Context<play::server::BlockChange>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.block_change_handlers.push(Box::new(self));
+    pub fn is_registered(&self) -> bool {
+        self.options.active.get()
     }
 }
 
-impl<F> PacketHandler<play::server::KickDisconnect> for F
-where
-    F: Fn(&Context<play::server::KickDisconnect>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.kick_disconnect_handlers.push(Box::new(self));
-    }
+pub struct Registered<T> {
+    handler: EventHandler<T>,
+    options: Rc<HandlerOptions>,
+    /// Registration order, breaks ties between equal priorities.
+    order: usize,
 }
 
-impl<F> PacketHandler<play::server::ServerDifficulty> for F
-where
-    F: Fn(&Context<play::server::ServerDifficulty>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.server_difficulty_handlers.push(Box::new(self));
+impl<T> Registered<T> {
+    fn sort_key(&self) -> (Reverse<i32>, usize) {
+        (Reverse(self.options.priority.get()), self.order)
     }
 }
 
-impl<F> PacketHandler<play::server::ScoreboardObjective> for F
-where
-    F: Fn(&Context<play::server::ScoreboardObjective>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.scoreboard_objective_handlers.push(Box::new(self));
-    }
+/// The handlers of one event, kept in the order they run in.
+pub struct Handlers<T> {
+    // Dispatching only needs `&EventHandlers`, the bot shares them across reconnects
+    list: RefCell<Vec<Registered<T>>>,
+    registered: usize,
 }
 
-impl<F> PacketHandler<play::server::ScoreboardUpdate> for F
-where
-    F: Fn(&Context<play::server::ScoreboardUpdate>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.scoreboard_update_handlers.push(Box::new(self));
-    }
-}
-
-impl<F> PacketHandler<play::server::ScoreboardDisplay> for F
-where
-    F: Fn(&Context<play::server::ScoreboardDisplay>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.scoreboard_display_handlers.push(Box::new(self));
+impl<T> Default for Handlers<T> {
+    fn default() -> Self {
+        Self {
+            list: RefCell::default(),
+            registered: 0,
+        }
     }
 }
 
-impl<F> PacketHandler<play::server::Teams> for F
-where
-    F: Fn(&Context<play::server::Teams>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.teams_handlers.push(Box::new(self));
-    }
-}
+impl<T> Handlers<T> {
+    pub(crate) fn push(&mut self, handler: EventHandler<T>) -> HandlerHandle {
+        let options = Rc::new(HandlerOptions {
+            priority: Cell::new(0),
+            once: Cell::new(false),
+            active: Cell::new(true),
+        });
 
-impl<F> PacketHandler<play::server::SetSlot> for F
-where
-    F: Fn(&Context<play::server::SetSlot>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.set_slot_handlers.push(Box::new(self));
-    }
-}
+        self.list.get_mut().push(Registered {
+            handler,
+            options: Rc::clone(&options),
+            order: self.registered,
+        });
+        self.registered += 1;
 
-impl<F> PacketHandler<play::server::EntityEquipment> for F
-where
-    F: Fn(&Context<play::server::EntityEquipment>) + 'static,
-{
-    fn register(self, events: &mut EventHandlers) {
-        events.entity_equipment_handlers.push(Box::new(self));
+        HandlerHandle { options }
     }
-}
 
-pub trait Dispatchable {
-    fn dispatch_packet_event(&self, bot: &Bot);
-}
-
-impl Dispatchable for Packets {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        match self {
-            Self::ServerKeepAlive(payload) => bot.events.dispatch(payload, bot),
-            Self::ServerChat(payload) => bot.events.dispatch(payload, bot),
-            Self::JoinGame(payload) => bot.events.dispatch(payload, bot),
-            Self::UpdateTime(payload) => bot.events.dispatch(payload, bot),
-            Self::SpawnPosition(payload) => bot.events.dispatch(payload, bot),
-            Self::UpdateHealth(payload) => bot.events.dispatch(payload, bot),
-            Self::Respawn(payload) => bot.events.dispatch(payload, bot),
-            Self::ServerPosition(payload) => bot.events.dispatch(payload, bot),
-            Self::ServerHeldItemSlot(payload) => bot.events.dispatch(payload, bot),
-            Self::Bed(payload) => bot.events.dispatch(payload, bot),
-            Self::Animation(payload) => bot.events.dispatch(payload, bot),
-            Self::Collect(payload) => bot.events.dispatch(payload, bot),
-            Self::SpawnPlayer(payload) => bot.events.dispatch(payload, bot),
-            Self::SpawnMob(payload) => bot.events.dispatch(payload, bot),
-            Self::SpawnEntityPainting(payload) => bot.events.dispatch(payload, bot),
-            Self::SpawnEntityExperienceOrb(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityVelocity(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityDestroy(payload) => bot.events.dispatch(payload, bot),
-            Self::Entity(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityRelativeMove(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityLook(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityMoveLook(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityTeleport(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityHeadRotation(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityStatus(payload) => bot.events.dispatch(payload, bot),
-            Self::AttachEntity(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityMetadata(payload) => bot.events.dispatch(payload, bot),
-            Self::EntityEffect(payload) => bot.events.dispatch(payload, bot),
-            Self::RemoveEntityEffect(payload) => bot.events.dispatch(payload, bot),
-            Self::BlockChange(payload) => bot.events.dispatch(payload, bot),
-            Self::KickDisconnect(payload) => bot.events.dispatch(payload, bot),
-            Self::ServerDifficulty(payload) => bot.events.dispatch(payload, bot),
-            Self::ScoreboardObjective(payload) => bot.events.dispatch(payload, bot),
-            Self::ScoreboardUpdate(payload) => bot.events.dispatch(payload, bot),
-            Self::ScoreboardDisplay(payload) => bot.events.dispatch(payload, bot),
-            Self::Teams(payload) => bot.events.dispatch(payload, bot),
-            Self::SetSlot(payload) => bot.events.dispatch(payload, bot),
-            _ => {}
+    /// Sorts the handlers again if a priority changed since the last dispatch. Does nothing
+    /// during a nested dispatch of the same event, which runs in the outer one's order.
+    fn sort(&self) {
+        let Ok(mut list) = self.list.try_borrow_mut() else {
+            return;
         };
-    }
-}
 
-impl Dispatchable for Context<'_, '_, play::server::KeepAlive> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.keep_alive_handlers {
-            event(self);
-        }
-    }
-}
+        let sorted = list
+            .windows(2)
+            .all(|pair| pair[0].sort_key() <= pair[1].sort_key());
 
-impl Dispatchable for Context<'_, '_, play::server::Chat> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.chat_handlers {
-            event(self);
+        // Keys are unique, so an unstable sort keeps ties in registration order too
+        if !sorted {
+            list.sort_unstable_by_key(Registered::sort_key);
         }
     }
-}
 
-impl Dispatchable for Context<'_, '_, play::server::JoinGame> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.join_game_handlers {
-            event(self);
+    /// Drops unregistered handlers, unless a dispatch of this event is still running.
+    fn prune(&self) {
+        if let Ok(mut list) = self.list.try_borrow_mut() {
+            list.retain(|registered| registered.options.active.get());
         }
     }
 }
 
-impl Dispatchable for Context<'_, '_, play::server::UpdateTime> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.update_time_handlers {
-            event(self);
-        }
-    }
+pub trait PacketHandler<T: ServerPacket, S, Marker> {
+    fn register(self, events: &mut EventHandlers) -> HandlerHandle;
 }
 
-impl Dispatchable for Context<'_, '_, play::server::SpawnPosition> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.spawn_position_handlers {
-            event(self);
-        }
+pub trait Dispatchable {
+    /// Returns `true` if a handler canceled the bot's built-in handling of the payload.
+    fn dispatch(&self, events: &EventHandlers, bot: &mut Bot) -> bool;
+}
+
+/// Generates a handler list and a `Dispatchable` impl for every event, plus a
+/// `PacketHandler` impl for every server packet, so a packet can't be registered without
+/// being dispatched or the other way around.
+macro_rules! event_handlers {
+    (
+        events { $($event:ident => $event_handlers:ident),* $(,)? }
+        packets { $($variant:ident($packet:ident) => $handlers:ident),* $(,)? }
+    ) => {
+        #[derive(Default)]
+        pub struct EventHandlers {
+            pub tick_handlers: Handlers<()>,
+            pub on_connect_handlers: Handlers<()>,
+            $(pub $event_handlers: Handlers<$event>,)*
+            $($handlers: Handlers<play::server::$packet>,)*
+        }
+
+        $(
+            impl Dispatchable for $event {
+                fn dispatch(&self, events: &EventHandlers, bot: &mut Bot) -> bool {
+                    EventHandlers::run(&events.$event_handlers, bot, self)
+                }
+            }
+        )*
+
+        $(
+            impl<F, S, M> PacketHandler<play::server::$packet, S, M> for F
+            where
+                F: IntoEventHandler<play::server::$packet, S, M>,
+            {
+                fn register(self, events: &mut EventHandlers) -> HandlerHandle {
+                    events.$handlers.push(self.into_handler())
+                }
+            }
+
+            impl Dispatchable for play::server::$packet {
+                fn dispatch(&self, events: &EventHandlers, bot: &mut Bot) -> bool {
+                    EventHandlers::run(&events.$handlers, bot, self)
+                }
+            }
+        )*
+
+        impl Dispatchable for Packets {
+            fn dispatch(&self, events: &EventHandlers, bot: &mut Bot) -> bool {
+                match self {
+                    $(Self::$variant(payload) => payload.dispatch(events, bot),)*
+                    // Handshake, status, login and client packets
+                    _ => false,
+                }
+            }
+        }
+    };
+}
+
+event_handlers! {
+    events {
+        BlockUpdate => block_update_handlers,
+        Death => death_handlers,
+        Spawn => spawn_handlers,
+        HealthChange => health_change_handlers,
+        EntitySpawn => entity_spawn_handlers,
+        EntityDespawn => entity_despawn_handlers,
+        EntityMove => entity_move_handlers,
+        PlayerJoin => player_join_handlers,
+        PlayerLeave => player_leave_handlers,
+        ItemPickup => item_pickup_handlers,
+        Hurt => hurt_handlers,
+        DimensionChange => dimension_change_handlers,
+        Reconnecting => reconnecting_handlers,
+        Reconnected => reconnected_handlers,
+    }
+
+    // Play SetCompression is left out, compression is only ever set during login
+    packets {
+        ServerKeepAlive(KeepAlive) => keep_alive_handlers,
+        JoinGame(JoinGame) => join_game_handlers,
+        ServerChat(Chat) => chat_handlers,
+        UpdateTime(UpdateTime) => update_time_handlers,
+        EntityEquipment(EntityEquipment) => entity_equipment_handlers,
+        SpawnPosition(SpawnPosition) => spawn_position_handlers,
+        UpdateHealth(UpdateHealth) => update_health_handlers,
+        Respawn(Respawn) => respawn_handlers,
+        ServerPosition(Position) => position_handlers,
+        ServerHeldItemSlot(HeldItemSlot) => held_item_slot_handlers,
+        Bed(Bed) => bed_handlers,
+        Animation(Animation) => animation_handlers,
+        SpawnPlayer(SpawnPlayer) => spawn_player_handlers,
+        Collect(Collect) => collect_handlers,
+        SpawnObject(SpawnObject) => spawn_object_handlers,
+        SpawnMob(SpawnMob) => spawn_mob_handlers,
+        SpawnEntityPainting(SpawnEntityPainting) => spawn_entity_painting_handlers,
+        SpawnEntityExperienceOrb(SpawnEntityExperienceOrb) => spawn_entity_experience_orb_handlers,
+        EntityVelocity(EntityVelocity) => entity_velocity_handlers,
+        EntityDestroy(EntityDestroy) => entity_destroy_handlers,
+        Entity(Entity) => entity_handlers,
+        EntityRelativeMove(EntityRelativeMove) => entity_relative_move_handlers,
+        EntityLook(EntityLook) => entity_look_handlers,
+        EntityMoveLook(EntityMoveLook) => entity_move_look_handlers,
+        EntityTeleport(EntityTeleport) => entity_teleport_handlers,
+        EntityHeadRotation(EntityHeadRotation) => entity_head_rotation_handlers,
+        EntityStatus(EntityStatus) => entity_status_handlers,
+        AttachEntity(AttachEntity) => attach_entity_handlers,
+        EntityMetadata(EntityMetadata) => entity_metadata_handlers,
+        EntityEffect(EntityEffect) => entity_effect_handlers,
+        RemoveEntityEffect(RemoveEntityEffect) => remove_entity_effect_handlers,
+        Experience(Experience) => experience_handlers,
+        UpdateAttributes(UpdateAttributes) => update_attributes_handlers,
+        MapChunk(MapChunk) => map_chunk_handlers,
+        MultiBlockChange(MultiBlockChange) => multi_block_change_handlers,
+        BlockChange(BlockChange) => block_change_handlers,
+        BlockAction(BlockAction) => block_action_handlers,
+        BlockBreakAnimation(BlockBreakAnimation) => block_break_animation_handlers,
+        MapChunkBulk(MapChunkBulk) => map_chunk_bulk_handlers,
+        Explosion(Explosion) => explosion_handlers,
+        WorldEvent(WorldEvent) => world_event_handlers,
+        NamedSoundEffect(NamedSoundEffect) => named_sound_effect_handlers,
+        WorldParticles(WorldParticles) => world_particles_handlers,
+        GameStateChange(GameStateChange) => game_state_change_handlers,
+        SpawnEntityWeather(SpawnEntityWeather) => spawn_entity_weather_handlers,
+        OpenWindow(OpenWindow) => open_window_handlers,
+        ServerCloseWindow(CloseWindow) => close_window_handlers,
+        SetSlot(SetSlot) => set_slot_handlers,
+        WindowItems(WindowItems) => window_items_handlers,
+        CraftProgressBar(CraftProgressBar) => craft_progress_bar_handlers,
+        ServerTransaction(Transaction) => transaction_handlers,
+        ServerUpdateSign(UpdateSign) => update_sign_handlers,
+        Map(Map) => map_handlers,
+        TileEntityData(TileEntityData) => tile_entity_data_handlers,
+        OpenSignEntity(OpenSignEntity) => open_sign_entity_handlers,
+        Statistics(Statistics) => statistics_handlers,
+        PlayerInfo(PlayerInfo) => player_info_handlers,
+        ServerAbilities(Abilities) => abilities_handlers,
+        ServerTabComplete(TabComplete) => tab_complete_handlers,
+        ScoreboardObjective(ScoreboardObjective) => scoreboard_objective_handlers,
+        ScoreboardUpdate(ScoreboardUpdate) => scoreboard_update_handlers,
+        ScoreboardDisplay(ScoreboardDisplay) => scoreboard_display_handlers,
+        Teams(Teams) => teams_handlers,
+        ServerCustomPayload(CustomPayload) => custom_payload_handlers,
+        KickDisconnect(KickDisconnect) => kick_disconnect_handlers,
+        ServerDifficulty(ServerDifficulty) => server_difficulty_handlers,
+        CombatEvent(CombatEvent) => combat_event_handlers,
+        Camera(Camera) => camera_handlers,
+        WorldBorder(WorldBorder) => world_border_handlers,
+        Title(Title) => title_handlers,
+        PlayerlistHeader(PlayerlistHeader) => playerlist_header_handlers,
+        ResourcePackSend(ResourcePackSend) => resource_pack_send_handlers,
+        UpdateEntityNbt(UpdateEntityNbt) => update_entity_nbt_handlers,
     }
 }
 
-impl Dispatchable for Context<'_, '_, play::server::UpdateHealth> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.update_health_handlers {
-            event(self);
-        }
-    }
-}
+impl EventHandlers {
+    /// Runs the handlers in priority order until one cancels, returns `true` if the bot's
+    /// built-in handling should be skipped.
+    pub(crate) fn run<T: Clone + 'static>(
+        handlers: &Handlers<T>,
+        bot: &mut Bot,
+        payload: &T,
+    ) -> bool {
+        bot.waiters.borrow_mut().resolve(payload);
 
-impl Dispatchable for Context<'_, '_, play::server::Respawn> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.respawn_handlers {
-            event(self);
-        }
-    }
-}
+        // Taken out of the bot so handlers can borrow both at the same time
+        let mut state = bot.state.take();
+        let mut cancel = Cancel::None;
 
-impl Dispatchable for Context<'_, '_, play::server::Position> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.position_handlers {
-            event(self);
-        }
-    }
-}
+        handlers.sort();
 
-impl Dispatchable for Context<'_, '_, play::server::HeldItemSlot> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.held_item_slot_handlers {
-            event(self);
-        }
-    }
-}
+        for registered in handlers.list.borrow().iter() {
+            let options = &registered.options;
 
-impl Dispatchable for Context<'_, '_, play::server::Bed> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.bed_handlers {
-            event(self);
-        }
-    }
-}
+            if !options.active.get() {
+                continue;
+            }
 
-impl Dispatchable for Context<'_, '_, play::server::Animation> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.animation_handlers {
-            event(self);
-        }
-    }
-}
+            match registered.handler {
+                EventHandler::Sync(ref handler) => {
+                    // Nested dispatches from inside a sync handler have no state to give
+                    let Some(ref mut state) = state else {
+                        continue;
+                    };
 
-impl Dispatchable for Context<'_, '_, play::server::Collect> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.collect_handlers {
-            event(self);
-        }
-    }
-}
+                    cancel = handler(bot, &mut **state, payload);
+                }
 
-impl Dispatchable for Context<'_, '_, play::server::SpawnPlayer> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.spawn_player_handlers {
-            event(self);
-        }
-    }
-}
+                EventHandler::Async(ref handler) => {
+                    let task = handler(AsyncContext {
+                        bot: bot.handle(),
+                        payload: payload.clone(),
+                    });
 
-impl Dispatchable for Context<'_, '_, play::server::SpawnMob> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.spawn_mob_handlers {
-            event(self);
-        }
-    }
-}
+                    bot.spawn(task);
+                }
+            }
 
-impl Dispatchable for Context<'_, '_, play::server::SpawnEntityPainting> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.spawn_entity_painting_handlers {
-            event(self);
-        }
-    }
-}
+            if options.once.get() {
+                options.active.set(false);
+            }
 
-impl Dispatchable for Context<'_, '_, play::server::SpawnEntityExperienceOrb> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.spawn_entity_experience_orb_handlers {
-            event(self);
+            if cancel != Cancel::None {
+                break;
+            }
         }
-    }
-}
 
-impl Dispatchable for Context<'_, '_, play::server::EntityVelocity> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_velocity_handlers {
-            event(self);
-        }
-    }
-}
+        handlers.prune();
+        bot.state = state;
 
-impl Dispatchable for Context<'_, '_, play::server::EntityDestroy> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_destroy_handlers {
-            event(self);
-        }
-    }
-}
-
-impl Dispatchable for Context<'_, '_, play::server::Entity> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_handlers {
-            event(self);
-        }
-    }
-}
-
-impl Dispatchable for Context<'_, '_, play::server::EntityRelativeMove> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_relative_move_handlers {
-            event(self);
-        }
-    }
-}
-
-impl Dispatchable for Context<'_, '_, play::server::EntityLook> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_look_handlers {
-            event(self);
-        }
-    }
-}
-
-impl Dispatchable for Context<'_, '_, play::server::EntityMoveLook> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_move_look_handlers {
-            event(self);
-        }
-    }
-}
-
-impl Dispatchable for Context<'_, '_, play::server::EntityTeleport> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_teleport_handlers {
-            event(self);
-        }
-    }
-}
-
-impl Dispatchable for Context<'_, '_, play::server::EntityHeadRotation> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_head_rotation_handlers {
-            event(self);
-        }
-    }
-}
-
-impl Dispatchable for Context<'_, '_, play::server::EntityStatus> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_status_handlers {
-            event(self);
-        }
-    }
-}
-
-impl Dispatchable for Context<'_, '_, play::server::AttachEntity> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.attach_entity_handlers {
-            event(self);
-        }
+        cancel == Cancel::Builtin
     }
 }
 
-impl Dispatchable for Context<'_, '_, play::server::EntityMetadata> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_metadata_handlers {
-            event(self);
-        }
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl Dispatchable for Context<'_, '_, play::server::EntityEffect> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_effect_handlers {
-            event(self);
-        }
+    fn handler() -> EventHandler<()> {
+        EventHandler::Sync(Box::new(|_, _, _| Cancel::None))
     }
-}
 
-impl Dispatchable for Context<'_, '_, play::server::RemoveEntityEffect> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.remove_entity_effect_handlers {
-            event(self);
-        }
+    fn order(handlers: &Handlers<()>) -> Vec<usize> {
+        handlers
+            .list
+            .borrow()
+            .iter()
+            .map(|registered| registered.order)
+            .collect()
     }
-}
 
-impl Dispatchable for Context<'_, '_, play::server::BlockChange> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.block_change_handlers {
-            event(self);
-        }
-    }
-}
+    #[test]
+    fn sorts_by_priority_then_registration() {
+        let mut handlers = Handlers::default();
+        let first = handlers.push(handler());
+        let second = handlers.push(handler()).with_priority(5);
+        let third = handlers.push(handler());
+        let _fourth = handlers.push(handler()).with_priority(5);
 
-impl Dispatchable for Context<'_, '_, play::server::KickDisconnect> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.kick_disconnect_handlers {
-            event(self);
-        }
-    }
-}
+        handlers.sort();
+        assert_eq!(order(&handlers), [1, 3, 0, 2]);
 
-impl Dispatchable for Context<'_, '_, play::server::ServerDifficulty> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.server_difficulty_handlers {
-            event(self);
-        }
-    }
-}
+        // Priorities changed after registration apply from the next dispatch
+        first.with_priority(10);
+        second.with_priority(0);
+        third.with_priority(-1);
 
-impl Dispatchable for Context<'_, '_, play::server::ScoreboardObjective> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.scoreboard_objective_handlers {
-            event(self);
-        }
+        handlers.sort();
+        assert_eq!(order(&handlers), [0, 3, 1, 2]);
     }
-}
 
-impl Dispatchable for Context<'_, '_, play::server::ScoreboardUpdate> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.scoreboard_update_handlers {
-            event(self);
-        }
-    }
-}
+    #[test]
+    fn prunes_unregistered_handlers() {
+        let mut handlers = Handlers::default();
+        let first = handlers.push(handler());
+        let second = handlers.push(handler()).once();
 
-impl Dispatchable for Context<'_, '_, play::server::ScoreboardDisplay> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.scoreboard_display_handlers {
-            event(self);
-        }
-    }
-}
+        first.unregister();
+        handlers.prune();
 
-impl Dispatchable for Context<'_, '_, play::server::Teams> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.teams_handlers {
-            event(self);
-        }
-    }
-}
+        assert_eq!(order(&handlers), [1]);
+        assert!(second.is_registered());
 
-impl Dispatchable for Context<'_, '_, play::server::SetSlot> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.set_slot_handlers {
-            event(self);
-        }
-    }
-}
+        // Not while the handlers are being iterated, as in a nested dispatch
+        second.unregister();
+        let list = handlers.list.borrow();
+        handlers.prune();
+        assert_eq!(list.len(), 1);
+        drop(list);
 
-impl Dispatchable for Context<'_, '_, play::server::EntityEquipment> {
-    fn dispatch_packet_event(&self, bot: &Bot) {
-        for event in &bot.events.entity_equipment_handlers {
-            event(self);
-        }
+        handlers.prune();
+        assert!(order(&handlers).is_empty());
     }
 }
@@ -0,0 +1,93 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::oneshot;
+
+use crate::packets::ServerPacket;
+use crate::wait::{SharedWaiters, Waiters};
+use crate::Bot;
+
+pub(crate) type Command = Box<dyn FnOnce(&mut Bot)>;
+pub(crate) type CommandQueue = Rc<RefCell<VecDeque<Command>>>;
+
+/// A handle async handlers use to act on the bot across `.await` points.
+///
+/// Commands are queued and applied by the bot in order, right after the packet batch, tick
+/// or task poll that queued them. They never run in the middle of packet handling, and
+/// always before the next packet batch is handled.
+#[derive(Clone)]
+pub struct BotHandle {
+    commands: Weak<RefCell<VecDeque<Command>>>,
+    waiters: Weak<RefCell<Waiters>>,
+}
+
+impl BotHandle {
+    pub(crate) fn new(commands: &CommandQueue, waiters: &SharedWaiters) -> Self {
+        Self {
+            commands: Rc::downgrade(commands),
+            waiters: Rc::downgrade(waiters),
+        }
+    }
+
+    /// Resolves with the next packet of type `T` matching the predicate, e.g.
+    /// `bot.wait_for::<WindowItems>(|items| items.window_id == id, timeout)`.
+    ///
+    /// The wait starts when this is called, not when the future is first awaited.
+    pub fn wait_for<T: ServerPacket + Clone + 'static>(
+        &self,
+        predicate: impl Fn(&T) -> bool + 'static,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<T>> {
+        let future = self
+            .waiters
+            .upgrade()
+            .map(|waiters| Waiters::register(&waiters, predicate, timeout));
+
+        async move {
+            match future {
+                Some(future) => future.await,
+                None => Err(anyhow!("Bot is disconnected")),
+            }
+        }
+    }
+
+    /// Runs `f` with mutable access to the bot and returns its result.
+    /// Fails if the bot disconnected before the command was applied.
+    pub async fn run<R: 'static>(&self, f: impl FnOnce(&mut Bot) -> R + 'static) -> Result<R> {
+        let (tx, rx) = oneshot::channel();
+
+        let Some(commands) = self.commands.upgrade() else {
+            return Err(anyhow!("Bot is disconnected"));
+        };
+
+        commands.borrow_mut().push_back(Box::new(move |bot| {
+            let _ = tx.send(f(bot));
+        }));
+
+        // Dropped along with the bot, which also drops the sender
+        drop(commands);
+
+        rx.await.map_err(|_| anyhow!("Bot is disconnected"))
+    }
+
+    pub async fn chat(&self, message: impl ToString) -> Result<()> {
+        let message = message.to_string();
+        self.run(move |bot| bot.chat(&message)).await
+    }
+
+    pub async fn respawn(&self) -> Result<()> {
+        self.run(|bot| bot.respawn()).await
+    }
+
+    pub async fn select_slot(&self, slot: i8) -> Result<()> {
+        self.run(move |bot| bot.select_slot(slot)).await
+    }
+
+    pub async fn attack_entity(&self, id: i32) -> Result<()> {
+        self.run(move |bot| bot.attack_entity(id)).await
+    }
+}
@@ -2,7 +2,7 @@ use std::array;
 
 use gami_mc_protocol::packets::play::server::Item;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Inventory {
     pub slots: [Option<Item>; 45],
     pub carried: Option<Item>,
@@ -1,12 +1,60 @@
+mod address;
+mod auth;
+mod blocks;
 mod bot;
+mod cache;
+mod chunk;
+mod crypto;
+mod entity;
 mod events;
+mod handle;
 mod inventory;
+mod math;
+mod pathfinding;
+mod physics;
+mod players;
+mod policy;
+mod proxy;
 mod scores;
+mod semantic;
+mod session;
+mod shared;
+mod spatial;
+pub mod status;
 mod stream;
+#[cfg(test)]
+mod test_server;
+mod wait;
 mod world;
 
+pub use address::{DnsResolver, Endpoint, ServerAddress, SrvResolver};
+pub use auth::{
+    Authenticator, DeviceCode, MicrosoftAuthenticator, MicrosoftEndpoints, Offline, TokenCache,
+};
 pub use bot::{Bot, BotBuilder};
+pub use cache::WorldCache;
+pub use chunk::{BlockPos, BlockState, Chunk, ChunkSection};
+pub use entity::Transform;
+pub use events::{AsyncContext, Context, HandlerHandle};
 pub use gami_mc_protocol::packets;
+pub use handle::BotHandle;
 pub use inventory::Inventory;
+pub use math::{Aabb, Vec3};
+pub use pathfinding::{
+    Goal, GoalBlock, GoalFollowEntity, GoalNear, GoalXZ, PathExecutor, PathStatus,
+};
+pub use physics::{Controls, PlayerPhysics};
+pub use players::{PlayerList, PlayerListEntry};
+pub use policy::{
+    DeathPolicy, DisconnectReason, Kicked, ReconnectPolicy, Reconnected, Reconnecting,
+};
+pub use proxy::{Proxy, ProxyKind};
 pub use scores::*;
-pub use world::World;
+pub use semantic::{
+    Death, DimensionChange, EntityDespawn, EntityMove, EntitySpawn, HealthChange, Hurt, ItemPickup,
+    PlayerJoin, PlayerLeave, Spawn,
+};
+pub use session::{MojangSessionService, Session, SessionService};
+pub use shared::{SharedChunks, SharedWorlds};
+pub use stream::OutgoingPacket;
+pub use world::{BlockUpdate, World};
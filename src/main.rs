@@ -1,24 +1,71 @@
-use events::Context;
 use gami_mc_protocol::registry::EntityKind;
 use packets::play::server::Chat;
 
+mod address;
+mod auth;
+mod blocks;
 mod bot;
+mod cache;
+mod chunk;
+mod crypto;
+mod entity;
 mod events;
+mod handle;
 mod inventory;
+mod math;
+mod pathfinding;
+mod physics;
+mod players;
+mod policy;
+mod proxy;
 mod scores;
+mod semantic;
+mod session;
+mod shared;
+mod spatial;
+pub mod status;
 mod stream;
+#[cfg(test)]
+mod test_server;
+mod wait;
 mod world;
 
+pub use address::{DnsResolver, Endpoint, ServerAddress, SrvResolver};
+pub use auth::{
+    Authenticator, DeviceCode, MicrosoftAuthenticator, MicrosoftEndpoints, Offline, TokenCache,
+};
 pub use bot::{Bot, BotBuilder};
+pub use cache::WorldCache;
+pub use chunk::{BlockPos, BlockState, Chunk, ChunkSection};
+pub use entity::Transform;
+pub use events::{AsyncContext, Context, HandlerHandle};
 pub use gami_mc_protocol::packets;
+pub use handle::BotHandle;
 pub use inventory::Inventory;
-pub use world::World;
+pub use math::{Aabb, Vec3};
+pub use pathfinding::{
+    Goal, GoalBlock, GoalFollowEntity, GoalNear, GoalXZ, PathExecutor, PathStatus,
+};
+pub use physics::{Controls, PlayerPhysics};
+pub use players::{PlayerList, PlayerListEntry};
+pub use policy::{
+    DeathPolicy, DisconnectReason, Kicked, ReconnectPolicy, Reconnected, Reconnecting,
+};
+pub use proxy::{Proxy, ProxyKind};
+pub use semantic::{
+    Death, DimensionChange, EntityDespawn, EntityMove, EntitySpawn, HealthChange, Hurt, ItemPickup,
+    PlayerJoin, PlayerLeave, Spawn,
+};
+pub use session::{MojangSessionService, Session, SessionService};
+pub use shared::{SharedChunks, SharedWorlds};
+pub use stream::OutgoingPacket;
+pub use world::{BlockUpdate, World};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let mut bot = BotBuilder::new();
 
-    bot.on_chat(|ctx: &Context<Chat>| {
+    bot.on_chat(|ctx: &mut Context<Chat>| {
         if ctx.payload.message.contains("attack") {
             for entity in ctx.bot.world().entities.values() {
                 ctx.bot.attack_entity(entity.as_entity().id());
@@ -53,7 +100,7 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
-    bot.on_tick(|ctx: &Context<()>| {
+    bot.on_tick(|ctx: &mut Context<()>| {
         for entity in ctx.bot.world().entities.values() {
             if let EntityKind::Pig(pig) = entity {
                 if pig.has_saddle {
@@ -63,7 +110,7 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
-    bot.on_connect(|ctx: &Context<()>| {
+    bot.on_connect(|ctx: &mut Context<()>| {
         ctx.bot.chat("Connected!");
         ctx.bot.chat(&format!("UUID: {}", ctx.bot.uuid()));
         ctx.bot.chat(&format!("Entity ID: {}", ctx.bot.entity_id()));
@@ -0,0 +1,210 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vec3 {
+    pub const ZERO: Self = Self::new(0.0, 0.0, 0.0);
+
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn length_squared(&self) -> f64 {
+        self.x * self.x + self.y * self.y + self.z * self.z
+    }
+
+    pub fn length(&self) -> f64 {
+        self.length_squared().sqrt()
+    }
+
+    pub fn distance(&self, other: Vec3) -> f64 {
+        (*self - other).length()
+    }
+
+    pub fn distance_squared(&self, other: Vec3) -> f64 {
+        (*self - other).length_squared()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let length = self.length();
+
+        match length < 1.0E-4 {
+            true => Self::ZERO,
+            false => *self * (1.0 / length),
+        }
+    }
+
+    pub fn dot(&self, other: Vec3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<f64> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn around(center: Vec3, radius: f64) -> Self {
+        let extent = Vec3::new(radius, radius, radius);
+        Self::new(center - extent, center + extent)
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x
+            && self.max.x > other.min.x
+            && self.min.y < other.max.y
+            && self.max.y > other.min.y
+            && self.min.z < other.max.z
+            && self.max.z > other.min.z
+    }
+
+    pub fn offset(&self, offset: Vec3) -> Self {
+        Self::new(self.min + offset, self.max + offset)
+    }
+
+    /// Grows the box in the direction of the motion, vanilla's `addCoord`.
+    pub fn expand_towards(&self, motion: Vec3) -> Self {
+        let mut aabb = *self;
+
+        if motion.x < 0.0 {
+            aabb.min.x += motion.x;
+        } else {
+            aabb.max.x += motion.x;
+        }
+
+        if motion.y < 0.0 {
+            aabb.min.y += motion.y;
+        } else {
+            aabb.max.y += motion.y;
+        }
+
+        if motion.z < 0.0 {
+            aabb.min.z += motion.z;
+        } else {
+            aabb.max.z += motion.z;
+        }
+
+        aabb
+    }
+
+    pub fn inflate(&self, x: f64, y: f64, z: f64) -> Self {
+        let extent = Vec3::new(x, y, z);
+        Self::new(self.min - extent, self.max + extent)
+    }
+
+    /// Clips a movement along the X axis so `other` stops at this box.
+    pub fn clip_x(&self, other: &Aabb, mut offset: f64) -> f64 {
+        if other.max.y <= self.min.y || other.min.y >= self.max.y {
+            return offset;
+        }
+
+        if other.max.z <= self.min.z || other.min.z >= self.max.z {
+            return offset;
+        }
+
+        if offset > 0.0 && other.max.x <= self.min.x {
+            offset = offset.min(self.min.x - other.max.x);
+        } else if offset < 0.0 && other.min.x >= self.max.x {
+            offset = offset.max(self.max.x - other.min.x);
+        }
+
+        offset
+    }
+
+    pub fn clip_y(&self, other: &Aabb, mut offset: f64) -> f64 {
+        if other.max.x <= self.min.x || other.min.x >= self.max.x {
+            return offset;
+        }
+
+        if other.max.z <= self.min.z || other.min.z >= self.max.z {
+            return offset;
+        }
+
+        if offset > 0.0 && other.max.y <= self.min.y {
+            offset = offset.min(self.min.y - other.max.y);
+        } else if offset < 0.0 && other.min.y >= self.max.y {
+            offset = offset.max(self.max.y - other.min.y);
+        }
+
+        offset
+    }
+
+    pub fn clip_z(&self, other: &Aabb, mut offset: f64) -> f64 {
+        if other.max.x <= self.min.x || other.min.x >= self.max.x {
+            return offset;
+        }
+
+        if other.max.y <= self.min.y || other.min.y >= self.max.y {
+            return offset;
+        }
+
+        if offset > 0.0 && other.max.z <= self.min.z {
+            offset = offset.min(self.min.z - other.max.z);
+        } else if offset < 0.0 && other.min.z >= self.max.z {
+            offset = offset.max(self.max.z - other.min.z);
+        }
+
+        offset
+    }
+}
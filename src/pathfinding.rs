@@ -0,0 +1,335 @@
+mod executor;
+mod goals;
+mod movements;
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::task::Poll;
+
+pub use executor::{PathExecutor, PathStatus};
+pub use goals::{Goal, GoalBlock, GoalFollowEntity, GoalNear, GoalXZ};
+pub use movements::Movement;
+
+use crate::chunk::BlockPos;
+use crate::World;
+
+/// Upper bound on expanded nodes so an unreachable goal doesn't freeze the bot.
+const MAX_NODES: usize = 20_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathNode {
+    pub pos: BlockPos,
+    pub movement: Movement,
+}
+
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub nodes: Vec<PathNode>,
+    /// `false` when the search gave up and the path only leads as close as it could get.
+    pub complete: bool,
+}
+
+struct OpenNode {
+    pos: BlockPos,
+    cost: f64,
+    estimate: f64,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    // Reversed so the binary heap pops the lowest estimate first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+/// A* from `start` (the block the bot's feet are in) towards the goal, run a slice at a
+/// time so a long search doesn't stall the bot's tick.
+pub struct PathSearch {
+    start: BlockPos,
+    open: BinaryHeap<OpenNode>,
+    costs: HashMap<BlockPos, f64>,
+    came_from: HashMap<BlockPos, PathNode>,
+    closest: (f64, BlockPos),
+    expanded: usize,
+}
+
+impl PathSearch {
+    pub fn new(world: &World, start: BlockPos, goal: &dyn Goal) -> Self {
+        let start_heuristic = goal.heuristic(world, start);
+
+        let open = BinaryHeap::from([OpenNode {
+            pos: start,
+            cost: 0.0,
+            estimate: start_heuristic,
+        }]);
+
+        Self {
+            start,
+            open,
+            costs: HashMap::from([(start, 0.0)]),
+            came_from: HashMap::new(),
+            closest: (start_heuristic, start),
+            expanded: 0,
+        }
+    }
+
+    /// Expands up to `budget` nodes. Ready with `None` when no step towards the goal exists.
+    pub fn step(&mut self, world: &World, goal: &dyn Goal, budget: usize) -> Poll<Option<Path>> {
+        for _ in 0..budget {
+            let Some(node) = self.open.pop() else {
+                return Poll::Ready(self.partial());
+            };
+
+            // Skip stale heap entries for nodes that were reached more cheaply since
+            if node.cost > self.costs[&node.pos] {
+                continue;
+            }
+
+            if goal.is_reached(world, node.pos) {
+                return Poll::Ready(Some(Path {
+                    nodes: reconstruct(&self.came_from, self.start, node.pos),
+                    complete: true,
+                }));
+            }
+
+            self.expanded += 1;
+            if self.expanded > MAX_NODES {
+                return Poll::Ready(self.partial());
+            }
+
+            for neighbour in movements::neighbours(world, node.pos) {
+                let cost = node.cost + neighbour.cost;
+
+                if self
+                    .costs
+                    .get(&neighbour.pos)
+                    .is_some_and(|&known| known <= cost)
+                {
+                    continue;
+                }
+
+                let heuristic = goal.heuristic(world, neighbour.pos);
+
+                if heuristic < self.closest.0 {
+                    self.closest = (heuristic, neighbour.pos);
+                }
+
+                self.costs.insert(neighbour.pos, cost);
+                self.came_from.insert(
+                    neighbour.pos,
+                    PathNode {
+                        pos: node.pos,
+                        movement: neighbour.movement,
+                    },
+                );
+
+                self.open.push(OpenNode {
+                    pos: neighbour.pos,
+                    cost,
+                    estimate: cost + heuristic,
+                });
+            }
+        }
+
+        Poll::Pending
+    }
+
+    /// The path to the node closest to the goal, once the search gave up.
+    fn partial(&self) -> Option<Path> {
+        let (_, closest) = self.closest;
+
+        match closest == self.start {
+            true => None,
+            false => Some(Path {
+                nodes: reconstruct(&self.came_from, self.start, closest),
+                complete: false,
+            }),
+        }
+    }
+}
+
+/// `came_from` maps a node to its parent and the movement used to leave the parent.
+fn reconstruct(
+    came_from: &HashMap<BlockPos, PathNode>,
+    start: BlockPos,
+    end: BlockPos,
+) -> Vec<PathNode> {
+    let mut nodes = vec![];
+    let mut current = end;
+
+    while current != start {
+        let parent = came_from[&current];
+
+        nodes.push(PathNode {
+            pos: current,
+            movement: parent.movement,
+        });
+
+        current = parent.pos;
+    }
+
+    nodes.push(PathNode {
+        pos: start,
+        movement: Movement::Start,
+    });
+
+    nodes.reverse();
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::BlockState;
+
+    const STONE: u16 = 1;
+
+    fn stone() -> BlockState {
+        BlockState::new(STONE, 0)
+    }
+
+    /// A stone floor at y 63 from -8 to 8 on both axes, the bot stands at y 64.
+    fn flat_world() -> World {
+        let mut world = World::empty(1);
+        world.fill(BlockPos::new(-8, 63, -8), BlockPos::new(8, 63, 8), stone());
+        world
+    }
+
+    fn search(world: &World, start: BlockPos, goal: &dyn Goal) -> Option<Path> {
+        let mut search = PathSearch::new(world, start, goal);
+
+        loop {
+            if let Poll::Ready(path) = search.step(world, goal, MAX_NODES) {
+                return path;
+            }
+        }
+    }
+
+    fn positions(path: &Path) -> Vec<BlockPos> {
+        path.nodes.iter().map(|node| node.pos).collect()
+    }
+
+    #[test]
+    fn straight_path() {
+        let world = flat_world();
+        let goal = GoalBlock(BlockPos::new(5, 64, 0));
+        let path = search(&world, BlockPos::new(0, 64, 0), &goal).unwrap();
+
+        assert!(path.complete);
+        assert_eq!(
+            positions(&path),
+            (0..=5).map(|x| BlockPos::new(x, 64, 0)).collect::<Vec<_>>()
+        );
+
+        assert_eq!(path.nodes[0].movement, Movement::Start);
+        assert!(path.nodes[1..]
+            .iter()
+            .all(|node| node.movement == Movement::Walk));
+    }
+
+    #[test]
+    fn jumps_over_a_block() {
+        let mut world = flat_world();
+        world.fill(BlockPos::new(2, 64, -8), BlockPos::new(2, 64, 8), stone());
+
+        let goal = GoalBlock(BlockPos::new(4, 64, 0));
+        let path = search(&world, BlockPos::new(0, 64, 0), &goal).unwrap();
+
+        assert!(path.complete);
+
+        let movements: Vec<_> = path.nodes.iter().map(|node| node.movement).collect();
+        assert_eq!(
+            movements,
+            [
+                Movement::Start,
+                Movement::Walk,
+                Movement::Jump,
+                Movement::Drop,
+                Movement::Walk
+            ]
+        );
+        assert_eq!(path.nodes[2].pos, BlockPos::new(2, 65, 0));
+    }
+
+    /// A platform at y 63 up to x 0 and a lower floor from x 1, `depth` blocks down.
+    fn ledge(depth: i32) -> World {
+        let mut world = World::empty(1);
+        world.fill(BlockPos::new(-8, 63, -8), BlockPos::new(0, 63, 8), stone());
+        world.fill(
+            BlockPos::new(1, 63 - depth, -8),
+            BlockPos::new(8, 63 - depth, 8),
+            stone(),
+        );
+        world
+    }
+
+    #[test]
+    fn drops_down_to_the_limit() {
+        let world = ledge(3);
+        let path = search(&world, BlockPos::new(-4, 64, 0), &GoalXZ::new(4, 0)).unwrap();
+
+        assert!(path.complete);
+        assert_eq!(path.nodes.last().unwrap().pos, BlockPos::new(4, 61, 0));
+        assert!(path
+            .nodes
+            .iter()
+            .any(|node| node.movement == Movement::Drop && node.pos == BlockPos::new(1, 61, 0)));
+    }
+
+    #[test]
+    fn unreachable_goal_returns_a_partial_path() {
+        // One block further than the bot is allowed to drop
+        let world = ledge(4);
+        let path = search(&world, BlockPos::new(-4, 64, 0), &GoalXZ::new(4, 0)).unwrap();
+
+        assert!(!path.complete);
+        assert_eq!(path.nodes.first().unwrap().pos, BlockPos::new(-4, 64, 0));
+        assert_eq!(path.nodes.last().unwrap().pos, BlockPos::new(0, 64, 0));
+    }
+
+    #[test]
+    fn no_path_when_nothing_gets_closer() {
+        let world = ledge(4);
+        let path = search(&world, BlockPos::new(0, 64, 0), &GoalXZ::new(4, 0));
+
+        assert!(path.is_none());
+    }
+
+    #[test]
+    fn search_is_sliced() {
+        let world = flat_world();
+        let goal = GoalBlock(BlockPos::new(6, 64, 6));
+        let start = BlockPos::new(-6, 64, -6);
+
+        let mut sliced = PathSearch::new(&world, start, &goal);
+        let mut slices = 1;
+
+        let path = loop {
+            match sliced.step(&world, &goal, 1) {
+                Poll::Ready(path) => break path.unwrap(),
+                Poll::Pending => slices += 1,
+            }
+        };
+
+        assert!(slices > 1);
+        assert!(path.complete);
+        assert_eq!(path.nodes.last().unwrap().pos, goal.0);
+
+        let whole = search(&world, start, &goal).unwrap();
+        assert_eq!(path.nodes.len(), whole.nodes.len());
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::task::Poll;
+
+use super::{Goal, Movement, Path, PathNode, PathSearch};
+use crate::blocks;
+use crate::chunk::BlockPos;
+use crate::math::Vec3;
+use crate::physics::{Controls, PlayerPhysics};
+use crate::world::BlockUpdate;
+use crate::World;
+
+/// How close to the center of a node the bot has to get before moving on to the next one.
+const REACH_DISTANCE: f64 = 0.35;
+const STUCK_TICKS: u32 = 60;
+const DYNAMIC_REPLAN_TICKS: u32 = 20;
+/// Search nodes expanded per tick, a long search is spread over several ticks.
+const NODES_PER_TICK: usize = 2_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStatus {
+    Moving,
+    Reached,
+    NoPath,
+}
+
+/// Follows a computed path by pressing the bot's movement keys, one physics tick at a time.
+pub struct PathExecutor {
+    goal: Box<dyn Goal>,
+    path: VecDeque<PathNode>,
+    complete: bool,
+    search: Option<PathSearch>,
+    /// Where the bot stood on the last tick, the start of the remaining path.
+    feet: Option<BlockPos>,
+    needs_replan: bool,
+    ticks_since_progress: u32,
+    ticks_since_plan: u32,
+}
+
+impl PathExecutor {
+    pub fn new(goal: impl Goal + 'static) -> Self {
+        Self {
+            goal: Box::new(goal),
+            path: VecDeque::new(),
+            complete: false,
+            search: None,
+            feet: None,
+            needs_replan: true,
+            ticks_since_progress: 0,
+            ticks_since_plan: 0,
+        }
+    }
+
+    pub fn goal(&self) -> &dyn Goal {
+        self.goal.as_ref()
+    }
+
+    pub fn path(&self) -> impl Iterator<Item = &PathNode> {
+        self.path.iter()
+    }
+
+    /// Replans when a changed block is in the way of a remaining step: the blocks between
+    /// its two ends, including diagonal corners, with their floor and headroom.
+    pub fn on_block_update(&mut self, update: &BlockUpdate) {
+        let changed = update.position;
+        let mut from = self.feet;
+
+        let affected = self.path.iter().any(|node| {
+            let to = node.pos;
+            let from = from.replace(to).unwrap_or(to);
+
+            (from.x.min(to.x)..=from.x.max(to.x)).contains(&changed.x)
+                && (from.z.min(to.z)..=from.z.max(to.z)).contains(&changed.z)
+                && (from.y.min(to.y) - 1..=from.y.max(to.y) + 1).contains(&changed.y)
+        });
+
+        if affected {
+            self.needs_replan = true;
+        }
+    }
+
+    fn should_replan(&self) -> bool {
+        self.needs_replan
+            || self.path.is_empty()
+            || self.ticks_since_progress >= STUCK_TICKS
+            || self.goal.is_dynamic() && self.ticks_since_plan >= DYNAMIC_REPLAN_TICKS
+    }
+
+    fn follow(&mut self, path: Option<Path>) -> bool {
+        let Some(path) = path else {
+            return false;
+        };
+
+        // The first node is where the bot stood when the search started
+        self.path = path.nodes.into_iter().skip(1).collect();
+        self.complete = path.complete;
+        self.ticks_since_progress = 0;
+        self.ticks_since_plan = 0;
+
+        !self.path.is_empty()
+    }
+
+    pub fn tick(
+        &mut self,
+        world: &World,
+        physics: &mut PlayerPhysics,
+        controls: &mut Controls,
+    ) -> PathStatus {
+        *controls = Controls::default();
+
+        let feet = BlockPos::new(
+            physics.position.x.floor() as i32,
+            physics.position.y.floor() as i32,
+            physics.position.z.floor() as i32,
+        );
+
+        if self.goal.is_reached(world, feet) && (physics.on_ground || physics.in_water) {
+            return PathStatus::Reached;
+        }
+
+        self.feet = Some(feet);
+
+        if self.search.is_none() && self.should_replan() {
+            // Cleared now so changes during the search trigger another one
+            self.needs_replan = false;
+            self.search = Some(PathSearch::new(world, feet, self.goal.as_ref()));
+        }
+
+        if let Some(ref mut search) = self.search {
+            match search.step(world, self.goal.as_ref(), NODES_PER_TICK) {
+                Poll::Ready(path) => {
+                    self.search = None;
+
+                    if !self.follow(path) {
+                        return PathStatus::NoPath;
+                    }
+                }
+
+                // Keep following the previous path, if any, until the search is done
+                Poll::Pending if self.path.is_empty() => return PathStatus::Moving,
+                Poll::Pending => {}
+            }
+        }
+
+        self.ticks_since_plan += 1;
+        self.ticks_since_progress += 1;
+
+        while let Some(node) = self.path.front() {
+            if !Self::has_reached(physics, node) {
+                break;
+            }
+
+            self.path.pop_front();
+            self.ticks_since_progress = 0;
+        }
+
+        let Some(&node) = self.path.front() else {
+            // A partial path ran out, try to get further from here
+            self.needs_replan = true;
+
+            return match self.complete {
+                true => PathStatus::Reached,
+                false => PathStatus::Moving,
+            };
+        };
+
+        Self::steer(world, physics, controls, node);
+
+        PathStatus::Moving
+    }
+
+    fn center(pos: BlockPos) -> Vec3 {
+        Vec3::new(pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5)
+    }
+
+    fn has_reached(physics: &PlayerPhysics, node: &PathNode) -> bool {
+        let target = Self::center(node.pos);
+        let dx = target.x - physics.position.x;
+        let dz = target.z - physics.position.z;
+
+        (dx * dx + dz * dz).sqrt() < REACH_DISTANCE && (physics.position.y - target.y).abs() < 0.5
+    }
+
+    fn steer(world: &World, physics: &mut PlayerPhysics, controls: &mut Controls, node: PathNode) {
+        let target = Self::center(node.pos);
+        let going_up = target.y > physics.position.y + 0.1;
+
+        let facing = match node.movement {
+            // Climbing only works while pushing against the ladder's wall
+            Movement::Climb if going_up => climb_direction(world, node.pos).unwrap_or(target),
+            _ => target,
+        };
+
+        let dx = facing.x - physics.position.x;
+        let dz = facing.z - physics.position.z;
+
+        if dx * dx + dz * dz > 1.0E-4 {
+            physics.yaw = (-dx.atan2(dz) * 180.0 / PI) as f32;
+        }
+
+        controls.forward = true;
+
+        match node.movement {
+            Movement::Jump => {
+                controls.jump = physics.on_ground && (physics.collided_horizontally || going_up);
+            }
+            Movement::Swim => controls.jump = going_up || physics.in_water,
+            Movement::Climb if !going_up => controls.forward = false,
+            _ => {}
+        }
+
+        // Keep the bot's head above water while it swims along
+        if physics.in_water && !going_up && target.y >= physics.position.y.floor() {
+            controls.jump = true;
+        }
+    }
+}
+
+/// A point inside the block a ladder is attached to, or any solid block next to a vine.
+fn climb_direction(world: &World, pos: BlockPos) -> Option<Vec3> {
+    let state = world.block_at(pos.x, pos.y, pos.z)?;
+    let center = Vec3::new(pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5);
+
+    let (dx, dz) = match (state.id(), state.metadata()) {
+        (blocks::LADDER, 2) => (0, 1),
+        (blocks::LADDER, 3) => (0, -1),
+        (blocks::LADDER, 4) => (1, 0),
+        (blocks::LADDER, 5) => (-1, 0),
+        _ => [(0, 1), (0, -1), (1, 0), (-1, 0)]
+            .into_iter()
+            .find(|&(dx, dz)| {
+                world
+                    .block_at(pos.x + dx, pos.y, pos.z + dz)
+                    .is_some_and(blocks::is_full_cube)
+            })?,
+    };
+
+    Some(center + Vec3::new(dx as f64, 0.0, dz as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::BlockState;
+    use crate::pathfinding::GoalBlock;
+
+    fn stone() -> BlockState {
+        BlockState::new(1, 0)
+    }
+
+    /// Runs the executor and the bot's physics together until the executor is done.
+    fn walk(
+        world: &World,
+        executor: &mut PathExecutor,
+        physics: &mut PlayerPhysics,
+        ticks: usize,
+    ) -> Option<PathStatus> {
+        let mut controls = Controls::default();
+
+        for _ in 0..ticks {
+            let status = executor.tick(world, physics, &mut controls);
+
+            if status != PathStatus::Moving {
+                return Some(status);
+            }
+
+            physics.tick(world, &controls);
+        }
+
+        None
+    }
+
+    #[test]
+    fn walks_and_jumps_to_the_goal() {
+        let mut world = World::empty(1);
+        world.fill(BlockPos::new(-8, 63, -8), BlockPos::new(8, 63, 8), stone());
+        world.fill(BlockPos::new(3, 64, -8), BlockPos::new(3, 64, 8), stone());
+
+        let goal = BlockPos::new(6, 64, 2);
+        let mut executor = PathExecutor::new(GoalBlock(goal));
+        let mut physics = PlayerPhysics::new(Vec3::new(0.5, 64.0, 0.5), 0.0, 0.0);
+
+        assert_eq!(
+            walk(&world, &mut executor, &mut physics, 200),
+            Some(PathStatus::Reached)
+        );
+
+        assert_eq!(physics.position.x.floor() as i32, goal.x);
+        assert_eq!(physics.position.y, 64.0);
+        assert_eq!(physics.position.z.floor() as i32, goal.z);
+    }
+
+    #[test]
+    fn long_searches_are_spread_over_ticks() {
+        let mut world = World::empty(4);
+        world.fill(
+            BlockPos::new(-50, 63, -50),
+            BlockPos::new(50, 63, 50),
+            stone(),
+        );
+
+        // Straight up, every walkable block gets expanded before giving up
+        let mut executor = PathExecutor::new(GoalBlock(BlockPos::new(0, 80, 0)));
+        let mut physics = PlayerPhysics::new(Vec3::new(0.5, 64.0, 0.5), 0.0, 0.0);
+        let mut controls = Controls::default();
+
+        let mut ticks = 0;
+
+        let status = loop {
+            ticks += 1;
+
+            match executor.tick(&world, &mut physics, &mut controls) {
+                PathStatus::Moving => {
+                    assert!(executor.search.is_some());
+                    assert_eq!(controls, Controls::default());
+                }
+                status => break status,
+            }
+        };
+
+        assert_eq!(status, PathStatus::NoPath);
+        assert!(ticks > (101 * 101) / NODES_PER_TICK);
+    }
+
+    #[test]
+    fn replans_when_a_block_on_the_path_changes() {
+        let mut world = World::empty(1);
+        world.fill(BlockPos::new(-8, 63, -8), BlockPos::new(8, 63, 8), stone());
+
+        let mut executor = PathExecutor::new(GoalBlock(BlockPos::new(6, 64, 0)));
+        let mut physics = PlayerPhysics::new(Vec3::new(0.5, 64.0, 0.5), 0.0, 0.0);
+        let mut controls = Controls::default();
+
+        executor.tick(&world, &mut physics, &mut controls);
+        assert!(executor.path().count() > 0);
+        assert!(!executor.needs_replan);
+
+        let update = |x, y, z| BlockUpdate {
+            position: BlockPos::new(x, y, z),
+            old: BlockState::AIR,
+            new: stone(),
+        };
+
+        // Off the path, and above the bot's head
+        executor.on_block_update(&update(3, 64, 4));
+        executor.on_block_update(&update(3, 66, 0));
+        assert!(!executor.needs_replan);
+
+        // The floor under the path
+        executor.on_block_update(&update(3, 63, 0));
+        assert!(executor.needs_replan);
+
+        executor.tick(&world, &mut physics, &mut controls);
+        assert!(!executor.needs_replan);
+
+        // In the way
+        world.set_block(3, 64, 0, stone());
+        executor.on_block_update(&update(3, 64, 0));
+        assert!(executor.needs_replan);
+
+        executor.tick(&world, &mut physics, &mut controls);
+
+        assert!(executor.path().count() > 0);
+        assert!(executor
+            .path()
+            .all(|node| node.pos != BlockPos::new(3, 64, 0)));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::chunk::BlockPos;
+use crate::World;
+
+/// Something the pathfinder can walk towards. Positions are the block the bot's feet are in.
+pub trait Goal {
+    fn is_reached(&self, world: &World, pos: BlockPos) -> bool;
+
+    /// Estimated cost left to reach the goal, it must never overestimate for paths to be
+    /// optimal.
+    fn heuristic(&self, world: &World, pos: BlockPos) -> f64;
+
+    /// Goals that move on their own (e.g. following an entity) are replanned regularly.
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+}
+
+fn distance(dx: f64, dy: f64, dz: f64) -> f64 {
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+/// Stand exactly in the given block.
+#[derive(Debug, Clone, Copy)]
+pub struct GoalBlock(pub BlockPos);
+
+impl Goal for GoalBlock {
+    fn is_reached(&self, _world: &World, pos: BlockPos) -> bool {
+        pos == self.0
+    }
+
+    fn heuristic(&self, _world: &World, pos: BlockPos) -> f64 {
+        distance(
+            (self.0.x - pos.x) as f64,
+            (self.0.y - pos.y) as f64,
+            (self.0.z - pos.z) as f64,
+        )
+    }
+}
+
+/// Get within `range` blocks of the given block.
+#[derive(Debug, Clone, Copy)]
+pub struct GoalNear {
+    pub pos: BlockPos,
+    pub range: f64,
+}
+
+impl GoalNear {
+    pub fn new(pos: BlockPos, range: f64) -> Self {
+        Self { pos, range }
+    }
+}
+
+impl Goal for GoalNear {
+    fn is_reached(&self, world: &World, pos: BlockPos) -> bool {
+        GoalBlock(self.pos).heuristic(world, pos) <= self.range
+    }
+
+    fn heuristic(&self, world: &World, pos: BlockPos) -> f64 {
+        (GoalBlock(self.pos).heuristic(world, pos) - self.range).max(0.0)
+    }
+}
+
+/// Reach the given column at any height.
+#[derive(Debug, Clone, Copy)]
+pub struct GoalXZ {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl GoalXZ {
+    pub fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+}
+
+impl Goal for GoalXZ {
+    fn is_reached(&self, _world: &World, pos: BlockPos) -> bool {
+        pos.x == self.x && pos.z == self.z
+    }
+
+    fn heuristic(&self, _world: &World, pos: BlockPos) -> f64 {
+        distance((self.x - pos.x) as f64, 0.0, (self.z - pos.z) as f64)
+    }
+}
+
+/// Stay within `range` blocks of a tracked entity, wherever it goes.
+#[derive(Debug, Clone, Copy)]
+pub struct GoalFollowEntity {
+    pub entity_id: i32,
+    pub range: f64,
+}
+
+impl GoalFollowEntity {
+    pub fn new(entity_id: i32, range: f64) -> Self {
+        Self { entity_id, range }
+    }
+
+    fn target(&self, world: &World) -> Option<BlockPos> {
+        let position = world.transform(self.entity_id)?.position;
+
+        Some(BlockPos::new(
+            position.x.floor() as i32,
+            position.y.floor() as i32,
+            position.z.floor() as i32,
+        ))
+    }
+}
+
+impl Goal for GoalFollowEntity {
+    fn is_reached(&self, world: &World, pos: BlockPos) -> bool {
+        // Nothing to walk to once the entity is gone
+        match self.target(world) {
+            Some(target) => GoalNear::new(target, self.range).is_reached(world, pos),
+            None => true,
+        }
+    }
+
+    fn heuristic(&self, world: &World, pos: BlockPos) -> f64 {
+        match self.target(world) {
+            Some(target) => GoalNear::new(target, self.range).heuristic(world, pos),
+            None => 0.0,
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goal_block() {
+        let world = World::empty(0);
+        let goal = GoalBlock(BlockPos::new(1, 64, 1));
+
+        assert!(goal.is_reached(&world, BlockPos::new(1, 64, 1)));
+        assert!(!goal.is_reached(&world, BlockPos::new(1, 65, 1)));
+        assert_eq!(goal.heuristic(&world, BlockPos::new(4, 60, 1)), 5.0);
+    }
+
+    #[test]
+    fn goal_near() {
+        let world = World::empty(0);
+        let goal = GoalNear::new(BlockPos::new(0, 64, 0), 2.0);
+
+        assert!(goal.is_reached(&world, BlockPos::new(2, 64, 0)));
+        assert!(!goal.is_reached(&world, BlockPos::new(2, 64, 1)));
+        assert_eq!(goal.heuristic(&world, BlockPos::new(1, 64, 0)), 0.0);
+        assert_eq!(goal.heuristic(&world, BlockPos::new(5, 64, 0)), 3.0);
+    }
+
+    #[test]
+    fn goal_xz_ignores_height() {
+        let world = World::empty(0);
+        let goal = GoalXZ::new(3, -2);
+
+        assert!(goal.is_reached(&world, BlockPos::new(3, 10, -2)));
+        assert!(goal.is_reached(&world, BlockPos::new(3, 200, -2)));
+        assert!(!goal.is_reached(&world, BlockPos::new(3, 64, -1)));
+        assert_eq!(goal.heuristic(&world, BlockPos::new(0, 0, 2)), 5.0);
+    }
+
+    #[test]
+    fn follow_a_missing_entity() {
+        let world = World::empty(0);
+        let goal = GoalFollowEntity::new(42, 3.0);
+
+        assert!(goal.is_dynamic());
+        assert!(goal.is_reached(&world, BlockPos::new(100, 64, 100)));
+        assert_eq!(goal.heuristic(&world, BlockPos::new(100, 64, 100)), 0.0);
+    }
+}
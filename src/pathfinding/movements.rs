@@ -0,0 +1,350 @@
+use std::f64::consts::SQRT_2;
+
+use crate::blocks;
+use crate::chunk::{BlockPos, BlockState};
+use crate::World;
+
+const MAX_DROP: i32 = 3;
+
+const WALK_COST: f64 = 1.0;
+const JUMP_COST: f64 = 2.0;
+const DROP_COST: f64 = 1.0;
+// At least a block per block fallen, or the goals' straight line distance would overestimate
+// drops and A* could miss the cheapest path
+const FALL_COST_PER_BLOCK: f64 = 1.0;
+const SWIM_COST: f64 = 2.0;
+const CLIMB_COST: f64 = 1.5;
+
+const FIRE: u16 = 51;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    Start,
+    Walk,
+    Jump,
+    Drop,
+    Swim,
+    Climb,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbour {
+    pub pos: BlockPos,
+    pub movement: Movement,
+    pub cost: f64,
+}
+
+fn state(world: &World, pos: BlockPos) -> Option<BlockState> {
+    world.block_at(pos.x, pos.y, pos.z)
+}
+
+fn is_dangerous(state: BlockState) -> bool {
+    blocks::is_lava(state) || blocks::is_web(state) || state.id() == FIRE
+}
+
+/// The bot's body can be in this block. Unloaded blocks are never passable.
+pub fn is_passable(world: &World, pos: BlockPos) -> bool {
+    let Some(state) = state(world, pos) else {
+        return false;
+    };
+
+    if is_dangerous(state) {
+        return false;
+    }
+
+    blocks::is_climbable(state) || blocks::collision_boxes(world, pos, state).is_empty()
+}
+
+/// The block can be stood on. Fences and walls are excluded as they can't be jumped on.
+pub fn is_standable(world: &World, pos: BlockPos) -> bool {
+    let Some(state) = state(world, pos) else {
+        return false;
+    };
+
+    if blocks::is_fence_like(state) || state.id() == blocks::CACTUS {
+        return false;
+    }
+
+    !blocks::collision_boxes(world, pos, state).is_empty()
+}
+
+pub fn is_water(world: &World, pos: BlockPos) -> bool {
+    state(world, pos).is_some_and(blocks::is_water)
+}
+
+pub fn is_climbable(world: &World, pos: BlockPos) -> bool {
+    state(world, pos).is_some_and(blocks::is_climbable)
+}
+
+/// The bot fits in the block and something keeps it from falling.
+pub fn can_stand_at(world: &World, pos: BlockPos) -> bool {
+    is_passable(world, pos)
+        && is_passable(world, pos.offset(0, 1, 0))
+        && (is_standable(world, pos.offset(0, -1, 0))
+            || is_water(world, pos)
+            || is_climbable(world, pos))
+}
+
+fn movement_into(world: &World, pos: BlockPos, movement: Movement, cost: f64) -> Neighbour {
+    match is_water(world, pos) {
+        true => Neighbour {
+            pos,
+            movement: Movement::Swim,
+            cost: cost.max(SWIM_COST),
+        },
+        false => Neighbour {
+            pos,
+            movement,
+            cost,
+        },
+    }
+}
+
+pub fn neighbours(world: &World, pos: BlockPos) -> Vec<Neighbour> {
+    const CARDINALS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    const DIAGONALS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+    let mut neighbours = Vec::with_capacity(12);
+
+    for (dx, dz) in CARDINALS {
+        let next = pos.offset(dx, 0, dz);
+
+        if can_stand_at(world, next) {
+            neighbours.push(movement_into(world, next, Movement::Walk, WALK_COST));
+            continue;
+        }
+
+        let blocked = !is_passable(world, next) || !is_passable(world, next.offset(0, 1, 0));
+
+        if blocked {
+            let above = next.offset(0, 1, 0);
+            let has_headroom = is_passable(world, pos.offset(0, 2, 0));
+
+            if has_headroom && is_standable(world, next) && can_stand_at(world, above) {
+                neighbours.push(movement_into(world, above, Movement::Jump, JUMP_COST));
+            }
+
+            continue;
+        }
+
+        for depth in 1..=MAX_DROP {
+            let fall = next.offset(0, -depth, 0);
+
+            if !is_passable(world, fall) {
+                break;
+            }
+
+            if can_stand_at(world, fall) {
+                let cost = DROP_COST + depth as f64 * FALL_COST_PER_BLOCK;
+                neighbours.push(movement_into(world, fall, Movement::Drop, cost));
+                break;
+            }
+        }
+    }
+
+    for (dx, dz) in DIAGONALS {
+        let next = pos.offset(dx, 0, dz);
+
+        // Both corners have to be free or the bot would clip into them
+        let corners_free = [pos.offset(dx, 0, 0), pos.offset(0, 0, dz)]
+            .into_iter()
+            .all(|corner| is_passable(world, corner) && is_passable(world, corner.offset(0, 1, 0)));
+
+        if corners_free && can_stand_at(world, next) {
+            neighbours.push(movement_into(
+                world,
+                next,
+                Movement::Walk,
+                SQRT_2 * WALK_COST,
+            ));
+        }
+    }
+
+    let up = pos.offset(0, 1, 0);
+    let down = pos.offset(0, -1, 0);
+
+    if is_climbable(world, pos) && can_stand_at(world, up) {
+        neighbours.push(Neighbour {
+            pos: up,
+            movement: Movement::Climb,
+            cost: CLIMB_COST,
+        });
+    }
+
+    if is_climbable(world, down) && is_passable(world, down) {
+        neighbours.push(Neighbour {
+            pos: down,
+            movement: Movement::Climb,
+            cost: CLIMB_COST,
+        });
+    }
+
+    if is_water(world, pos) && is_passable(world, up.offset(0, 1, 0)) && is_passable(world, up) {
+        neighbours.push(Neighbour {
+            pos: up,
+            movement: Movement::Swim,
+            cost: SWIM_COST,
+        });
+    }
+
+    if is_water(world, down) {
+        neighbours.push(Neighbour {
+            pos: down,
+            movement: Movement::Swim,
+            cost: SWIM_COST,
+        });
+    }
+
+    neighbours
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stone() -> BlockState {
+        BlockState::new(1, 0)
+    }
+
+    fn flat_world() -> World {
+        let mut world = World::empty(1);
+        world.fill(BlockPos::new(-8, 63, -8), BlockPos::new(8, 63, 8), stone());
+        world
+    }
+
+    fn find(neighbours: &[Neighbour], pos: BlockPos) -> Option<Neighbour> {
+        neighbours
+            .iter()
+            .find(|neighbour| neighbour.pos == pos)
+            .copied()
+    }
+
+    #[test]
+    fn walks_in_eight_directions() {
+        let world = flat_world();
+        let neighbours = neighbours(&world, BlockPos::new(0, 64, 0));
+
+        assert_eq!(neighbours.len(), 8);
+        assert!(neighbours.iter().all(|n| n.movement == Movement::Walk));
+
+        assert_eq!(
+            find(&neighbours, BlockPos::new(1, 64, 0)).unwrap().cost,
+            WALK_COST
+        );
+        assert_eq!(
+            find(&neighbours, BlockPos::new(1, 64, 1)).unwrap().cost,
+            SQRT_2
+        );
+    }
+
+    #[test]
+    fn diagonals_need_both_corners_free() {
+        let mut world = flat_world();
+        world.set_block(1, 65, 0, stone());
+
+        let neighbours = neighbours(&world, BlockPos::new(0, 64, 0));
+
+        // The block only blocks the head, so it can't be jumped on either
+        assert!(find(&neighbours, BlockPos::new(1, 64, 0)).is_none());
+        assert!(find(&neighbours, BlockPos::new(1, 64, 1)).is_none());
+        assert!(find(&neighbours, BlockPos::new(1, 64, -1)).is_none());
+        assert!(find(&neighbours, BlockPos::new(-1, 64, 1)).is_some());
+    }
+
+    #[test]
+    fn jumps_need_headroom() {
+        let mut world = flat_world();
+        world.set_block(1, 64, 0, stone());
+
+        let jump = find(
+            &neighbours(&world, BlockPos::new(0, 64, 0)),
+            BlockPos::new(1, 65, 0),
+        );
+        assert_eq!(jump.unwrap().movement, Movement::Jump);
+        assert_eq!(jump.unwrap().cost, JUMP_COST);
+
+        world.set_block(0, 66, 0, stone());
+        let jump = find(
+            &neighbours(&world, BlockPos::new(0, 64, 0)),
+            BlockPos::new(1, 65, 0),
+        );
+        assert!(jump.is_none());
+    }
+
+    #[test]
+    fn never_jumps_on_fences() {
+        let mut world = flat_world();
+        world.set_block(1, 64, 0, BlockState::new(blocks::FENCE, 0));
+
+        let neighbours = neighbours(&world, BlockPos::new(0, 64, 0));
+
+        assert!(find(&neighbours, BlockPos::new(1, 64, 0)).is_none());
+        assert!(find(&neighbours, BlockPos::new(1, 65, 0)).is_none());
+    }
+
+    #[test]
+    fn drops_at_most_three_blocks() {
+        let mut world = World::empty(1);
+        world.set_block(0, 63, 0, stone());
+
+        for (x, depth) in [(1, 1), (-1, MAX_DROP)] {
+            world.set_block(x, 63 - depth, 0, stone());
+        }
+        world.set_block(0, 63 - MAX_DROP - 1, 1, stone());
+
+        let neighbours = neighbours(&world, BlockPos::new(0, 64, 0));
+
+        let shallow = find(&neighbours, BlockPos::new(1, 63, 0)).unwrap();
+        assert_eq!(shallow.movement, Movement::Drop);
+        assert_eq!(shallow.cost, DROP_COST + FALL_COST_PER_BLOCK);
+
+        let deepest = find(&neighbours, BlockPos::new(-1, 64 - MAX_DROP, 0)).unwrap();
+        assert_eq!(
+            deepest.cost,
+            DROP_COST + MAX_DROP as f64 * FALL_COST_PER_BLOCK
+        );
+
+        assert_eq!(neighbours.len(), 2);
+    }
+
+    #[test]
+    fn avoids_lava() {
+        let mut world = flat_world();
+        world.set_block(1, 64, 0, BlockState::new(blocks::LAVA, 0));
+
+        let neighbours = neighbours(&world, BlockPos::new(0, 64, 0));
+        assert!(find(&neighbours, BlockPos::new(1, 64, 0)).is_none());
+    }
+
+    #[test]
+    fn climbs_ladders_and_swims() {
+        let mut world = flat_world();
+        world.fill(BlockPos::new(-1, 64, 0), BlockPos::new(-1, 66, 0), stone());
+        world.fill(
+            BlockPos::new(0, 64, 0),
+            BlockPos::new(0, 66, 0),
+            BlockState::new(blocks::LADDER, 5),
+        );
+
+        let up = find(
+            &neighbours(&world, BlockPos::new(0, 64, 0)),
+            BlockPos::new(0, 65, 0),
+        );
+        assert_eq!(up.unwrap().movement, Movement::Climb);
+
+        let mut world = flat_world();
+        world.fill(
+            BlockPos::new(-8, 64, -8),
+            BlockPos::new(8, 65, 8),
+            BlockState::new(blocks::STATIONARY_WATER, 0),
+        );
+
+        let neighbours = neighbours(&world, BlockPos::new(0, 65, 0));
+        let down = find(&neighbours, BlockPos::new(0, 64, 0)).unwrap();
+        let side = find(&neighbours, BlockPos::new(1, 65, 0)).unwrap();
+
+        assert_eq!(down.movement, Movement::Swim);
+        assert_eq!(side.movement, Movement::Swim);
+        assert_eq!(side.cost, SWIM_COST);
+    }
+}
@@ -0,0 +1,803 @@
+use std::f64::consts::PI;
+
+use crate::blocks;
+use crate::chunk::{BlockPos, BlockState};
+use crate::math::{Aabb, Vec3};
+use crate::World;
+
+const WIDTH: f64 = 0.6;
+const HEIGHT: f64 = 1.8;
+pub const EYE_HEIGHT: f64 = 1.62;
+const STEP_HEIGHT: f64 = 0.6;
+
+const GRAVITY: f64 = 0.08;
+const AIR_DRAG: f64 = 0.9800000190734863;
+const INPUT_DRAG: f64 = 0.98;
+const BASE_FRICTION: f64 = 0.91;
+const GROUND_ACCELERATION: f64 = 0.16277136;
+
+const WALK_SPEED: f64 = 0.1;
+const SPRINT_SPEED: f64 = 0.13;
+const AIR_ACCELERATION: f64 = 0.02;
+const SPRINT_AIR_ACCELERATION: f64 = 0.026;
+const LIQUID_ACCELERATION: f64 = 0.02;
+const WATER_PUSH: f64 = 0.014;
+
+const JUMP_VELOCITY: f64 = 0.42;
+const SPRINT_JUMP_BOOST: f64 = 0.2;
+const JUMP_COOLDOWN: u8 = 10;
+const SNEAK_MULTIPLIER: f64 = 0.3;
+const SNEAK_EDGE_STEP: f64 = 0.05;
+
+const LADDER_SPEED: f64 = 0.15;
+const LADDER_CLIMB_VELOCITY: f64 = 0.2;
+const LIQUID_EXIT_VELOCITY: f64 = 0.3;
+const MIN_VELOCITY: f64 = 0.005;
+
+/// The movement keys held by the bot, read on every physics tick.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Controls {
+    pub forward: bool,
+    pub back: bool,
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+    pub sprint: bool,
+    pub sneak: bool,
+}
+
+impl Controls {
+    fn input(&self) -> (f64, f64) {
+        let axis = |positive: bool, negative: bool| positive as i8 as f64 - negative as i8 as f64;
+        let mut strafe = axis(self.left, self.right);
+        let mut forward = axis(self.forward, self.back);
+
+        if self.sneak {
+            strafe *= SNEAK_MULTIPLIER;
+            forward *= SNEAK_MULTIPLIER;
+        }
+
+        (strafe, forward)
+    }
+}
+
+/// A port of the 1.8 client movement code (`EntityLivingBase.moveEntityWithHeading` and
+/// `Entity.moveEntity`) for the bot's own player.
+#[derive(Debug, Default, Clone)]
+pub struct PlayerPhysics {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+    pub collided_horizontally: bool,
+    pub in_water: bool,
+    pub in_lava: bool,
+    pub sprinting: bool,
+    pub sneaking: bool,
+    in_web: bool,
+    jump_ticks: u8,
+}
+
+impl PlayerPhysics {
+    pub fn new(position: Vec3, yaw: f32, pitch: f32) -> Self {
+        Self {
+            position,
+            yaw,
+            pitch,
+            ..Default::default()
+        }
+    }
+
+    /// Applies a server teleport. Each bit of `flags` marks a field as relative to the current
+    /// value: x, y, z, yaw then pitch. Velocity is kept only on the relative axes.
+    pub fn teleport(&mut self, position: Vec3, yaw: f32, pitch: f32, flags: u8) {
+        const X: u8 = 0x01;
+        const Y: u8 = 0x02;
+        const Z: u8 = 0x04;
+        const YAW: u8 = 0x08;
+        const PITCH: u8 = 0x10;
+
+        let relative = |bit: u8, value: f64, current: f64| match flags & bit {
+            0 => value,
+            _ => current + value,
+        };
+
+        if flags & X == 0 {
+            self.velocity.x = 0.0;
+        }
+
+        if flags & Y == 0 {
+            self.velocity.y = 0.0;
+        }
+
+        if flags & Z == 0 {
+            self.velocity.z = 0.0;
+        }
+
+        self.position = Vec3::new(
+            relative(X, position.x, self.position.x),
+            relative(Y, position.y, self.position.y),
+            relative(Z, position.z, self.position.z),
+        );
+
+        self.yaw = relative(YAW, yaw as f64, self.yaw as f64) as f32;
+        self.pitch = relative(PITCH, pitch as f64, self.pitch as f64) as f32;
+    }
+
+    pub fn eye_position(&self) -> Vec3 {
+        self.position + Vec3::new(0.0, EYE_HEIGHT, 0.0)
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        Self::bounding_box_at(self.position)
+    }
+
+    fn bounding_box_at(position: Vec3) -> Aabb {
+        let half = WIDTH / 2.0;
+
+        Aabb::new(
+            Vec3::new(position.x - half, position.y, position.z - half),
+            Vec3::new(position.x + half, position.y + HEIGHT, position.z + half),
+        )
+    }
+
+    pub fn tick(&mut self, world: &World, controls: &Controls) {
+        // Vanilla freezes the player until the chunk it stands in is received
+        let (cx, cz) = (
+            (self.position.x.floor() as i32) >> 4,
+            (self.position.z.floor() as i32) >> 4,
+        );
+
+        if !world.is_chunk_loaded(cx, cz) {
+            return;
+        }
+
+        self.sneaking = controls.sneak;
+        self.sprinting =
+            controls.sprint && controls.forward && !controls.sneak && !self.collided_horizontally;
+
+        self.in_water = self.handle_water(world);
+        self.in_lava = self.touches(
+            world,
+            self.bounding_box().inflate(-0.1, -0.4, -0.1),
+            blocks::is_lava,
+        );
+
+        if self.jump_ticks > 0 {
+            self.jump_ticks -= 1;
+        }
+
+        for axis in [
+            &mut self.velocity.x,
+            &mut self.velocity.y,
+            &mut self.velocity.z,
+        ] {
+            if axis.abs() < MIN_VELOCITY {
+                *axis = 0.0;
+            }
+        }
+
+        if controls.jump {
+            if self.in_water || self.in_lava {
+                self.velocity.y += 0.04;
+            } else if self.on_ground && self.jump_ticks == 0 {
+                self.jump();
+                self.jump_ticks = JUMP_COOLDOWN;
+            }
+        } else {
+            self.jump_ticks = 0;
+        }
+
+        let (strafe, forward) = controls.input();
+        self.travel(world, strafe * INPUT_DRAG, forward * INPUT_DRAG);
+    }
+
+    fn jump(&mut self) {
+        self.velocity.y = JUMP_VELOCITY;
+
+        if self.sprinting {
+            let yaw = self.yaw as f64 * PI / 180.0;
+            self.velocity.x -= yaw.sin() * SPRINT_JUMP_BOOST;
+            self.velocity.z += yaw.cos() * SPRINT_JUMP_BOOST;
+        }
+    }
+
+    fn travel(&mut self, world: &World, strafe: f64, forward: f64) {
+        if self.in_water || self.in_lava {
+            let drag = if self.in_water { 0.8 } else { 0.5 };
+            let start_y = self.position.y;
+
+            self.accelerate(strafe, forward, LIQUID_ACCELERATION);
+            self.move_by(world, self.velocity);
+
+            self.velocity = self.velocity * drag;
+            self.velocity.y -= 0.02;
+
+            let exit = Vec3::new(
+                self.velocity.x,
+                self.velocity.y + 0.6 - self.position.y + start_y,
+                self.velocity.z,
+            );
+
+            if self.collided_horizontally && self.is_free(world, self.bounding_box().offset(exit)) {
+                self.velocity.y = LIQUID_EXIT_VELOCITY;
+            }
+
+            return;
+        }
+
+        let friction = self.friction(world);
+
+        let acceleration = match (self.on_ground, self.sprinting) {
+            (true, sprinting) => {
+                let speed = if sprinting { SPRINT_SPEED } else { WALK_SPEED };
+                speed * GROUND_ACCELERATION / (friction * friction * friction)
+            }
+            (false, true) => SPRINT_AIR_ACCELERATION,
+            (false, false) => AIR_ACCELERATION,
+        };
+
+        self.accelerate(strafe, forward, acceleration);
+
+        let on_ladder = self.on_ladder(world);
+
+        if on_ladder {
+            self.velocity.x = self.velocity.x.clamp(-LADDER_SPEED, LADDER_SPEED);
+            self.velocity.z = self.velocity.z.clamp(-LADDER_SPEED, LADDER_SPEED);
+            self.velocity.y = self.velocity.y.max(-LADDER_SPEED);
+
+            if self.sneaking && self.velocity.y < 0.0 {
+                self.velocity.y = 0.0;
+            }
+        }
+
+        self.move_by(world, self.velocity);
+
+        if self.collided_horizontally && on_ladder {
+            self.velocity.y = LADDER_CLIMB_VELOCITY;
+        }
+
+        self.velocity.y = (self.velocity.y - GRAVITY) * AIR_DRAG;
+        self.velocity.x *= friction;
+        self.velocity.z *= friction;
+    }
+
+    /// Vanilla's `moveFlying`, turns the movement input into velocity along the yaw.
+    fn accelerate(&mut self, strafe: f64, forward: f64, acceleration: f64) {
+        let length = strafe * strafe + forward * forward;
+
+        if length < 1.0E-4 {
+            return;
+        }
+
+        let scale = acceleration / length.sqrt().max(1.0);
+        let (strafe, forward) = (strafe * scale, forward * scale);
+
+        let yaw = self.yaw as f64 * PI / 180.0;
+        let (sin, cos) = (yaw.sin(), yaw.cos());
+
+        self.velocity.x += strafe * cos - forward * sin;
+        self.velocity.z += forward * cos + strafe * sin;
+    }
+
+    fn friction(&self, world: &World) -> f64 {
+        if !self.on_ground {
+            return BASE_FRICTION;
+        }
+
+        let below = self.block(
+            world,
+            self.position.x,
+            self.position.y - 1.0,
+            self.position.z,
+        );
+        blocks::slipperiness(below) * BASE_FRICTION
+    }
+
+    fn on_ladder(&self, world: &World) -> bool {
+        let state = self.block(world, self.position.x, self.position.y, self.position.z);
+        blocks::is_climbable(state)
+    }
+
+    fn block(&self, world: &World, x: f64, y: f64, z: f64) -> BlockState {
+        world
+            .block_at(x.floor() as i32, y.floor() as i32, z.floor() as i32)
+            .unwrap_or(BlockState::AIR)
+    }
+
+    fn blocks_in(area: Aabb) -> impl Iterator<Item = BlockPos> {
+        let (min_x, max_x) = (area.min.x.floor() as i32, area.max.x.floor() as i32);
+        let (min_y, max_y) = (area.min.y.floor() as i32, area.max.y.floor() as i32);
+        let (min_z, max_z) = (area.min.z.floor() as i32, area.max.z.floor() as i32);
+
+        (min_x..=max_x).flat_map(move |x| {
+            (min_y..=max_y).flat_map(move |y| (min_z..=max_z).map(move |z| BlockPos::new(x, y, z)))
+        })
+    }
+
+    /// Vanilla's `handleMaterialAcceleration` for water: returns whether the bot is in it and
+    /// adds the push of its current to the velocity.
+    fn handle_water(&mut self, world: &World) -> bool {
+        let area = self.bounding_box().inflate(-0.001, -0.401, -0.001);
+        // Vanilla compares each block's water surface against this, not the bot's feet
+        let top = (area.max.y + 1.0).floor();
+
+        let mut in_water = false;
+        let mut flow = Vec3::ZERO;
+
+        for pos in Self::blocks_in(area) {
+            let Some(state) = world.block_at(pos.x, pos.y, pos.z) else {
+                continue;
+            };
+
+            if !blocks::is_water(state) {
+                continue;
+            }
+
+            let surface = (pos.y + 1) as f64 - blocks::liquid_height(state);
+
+            if top >= surface {
+                in_water = true;
+                flow += blocks::water_flow(world, pos);
+            }
+        }
+
+        if flow.length() > 0.0 {
+            self.velocity += flow.normalize() * WATER_PUSH;
+        }
+
+        in_water
+    }
+
+    fn touches(&self, world: &World, area: Aabb, predicate: fn(BlockState) -> bool) -> bool {
+        Self::blocks_in(area).any(|pos| world.block_at(pos.x, pos.y, pos.z).is_some_and(predicate))
+    }
+
+    fn collisions(&self, world: &World, area: Aabb) -> Vec<Aabb> {
+        // Fences and walls are taller than a block, so look one block lower as well
+        let search = Aabb::new(area.min - Vec3::new(0.0, 1.0, 0.0), area.max);
+
+        Self::blocks_in(search)
+            .flat_map(|pos| {
+                let state = world
+                    .block_at(pos.x, pos.y, pos.z)
+                    .unwrap_or(BlockState::AIR);
+                blocks::collision_boxes(world, pos, state)
+            })
+            .filter(|shape| shape.intersects(&area))
+            .collect()
+    }
+
+    fn is_free(&self, world: &World, area: Aabb) -> bool {
+        self.collisions(world, area).is_empty() && !self.touches(world, area, blocks::is_liquid)
+    }
+
+    /// Vanilla's `moveEntity`: clips the motion against the surrounding blocks, handles
+    /// sneaking on edges and stepping up, and updates the collision flags.
+    fn move_by(&mut self, world: &World, motion: Vec3) {
+        let mut motion = motion;
+
+        if self.in_web {
+            self.in_web = false;
+            motion.x *= 0.25;
+            motion.y *= 0.05;
+            motion.z *= 0.25;
+            self.velocity = Vec3::ZERO;
+        }
+
+        let mut bb = self.bounding_box();
+
+        if self.on_ground && self.sneaking {
+            motion = self.clip_to_edge(world, bb, motion);
+        }
+
+        let requested = motion;
+        let boxes = self.collisions(world, bb.expand_towards(motion));
+
+        motion.y = boxes.iter().fold(motion.y, |y, shape| shape.clip_y(&bb, y));
+        bb = bb.offset(Vec3::new(0.0, motion.y, 0.0));
+
+        let can_step = self.on_ground || (requested.y != motion.y && requested.y < 0.0);
+
+        motion.x = boxes.iter().fold(motion.x, |x, shape| shape.clip_x(&bb, x));
+        bb = bb.offset(Vec3::new(motion.x, 0.0, 0.0));
+
+        motion.z = boxes.iter().fold(motion.z, |z, shape| shape.clip_z(&bb, z));
+        bb = bb.offset(Vec3::new(0.0, 0.0, motion.z));
+
+        if can_step && (requested.x != motion.x || requested.z != motion.z) {
+            let (stepped, stepped_bb) = self.step_up(world, requested);
+            let horizontal = |v: Vec3| v.x * v.x + v.z * v.z;
+
+            if horizontal(stepped) > horizontal(motion) {
+                motion = stepped;
+                bb = stepped_bb;
+            }
+        }
+
+        self.position = Vec3::new(
+            (bb.min.x + bb.max.x) / 2.0,
+            bb.min.y,
+            (bb.min.z + bb.max.z) / 2.0,
+        );
+
+        self.collided_horizontally = requested.x != motion.x || requested.z != motion.z;
+        let collided_vertically = requested.y != motion.y;
+        self.on_ground = collided_vertically && requested.y < 0.0;
+
+        if requested.x != motion.x {
+            self.velocity.x = 0.0;
+        }
+
+        if requested.z != motion.z {
+            self.velocity.z = 0.0;
+        }
+
+        if collided_vertically {
+            let below = self.block(
+                world,
+                self.position.x,
+                self.position.y - 0.2,
+                self.position.z,
+            );
+
+            self.velocity.y = match blocks::is_slime(below) && !self.sneaking {
+                true if self.velocity.y < 0.0 => -self.velocity.y,
+                _ => 0.0,
+            };
+        }
+
+        self.apply_block_effects(world);
+    }
+
+    /// Shrinks the horizontal motion until the bot would still stand on something, which is
+    /// what keeps sneaking players from walking off edges.
+    fn clip_to_edge(&self, world: &World, bb: Aabb, mut motion: Vec3) -> Vec3 {
+        let shrink = |value: f64| {
+            if value.abs() < SNEAK_EDGE_STEP {
+                0.0
+            } else {
+                value - SNEAK_EDGE_STEP * value.signum()
+            }
+        };
+
+        let unsupported = |x: f64, z: f64| {
+            self.collisions(world, bb.offset(Vec3::new(x, -1.0, z)))
+                .is_empty()
+        };
+
+        while motion.x != 0.0 && unsupported(motion.x, 0.0) {
+            motion.x = shrink(motion.x);
+        }
+
+        while motion.z != 0.0 && unsupported(0.0, motion.z) {
+            motion.z = shrink(motion.z);
+        }
+
+        while motion.x != 0.0 && motion.z != 0.0 && unsupported(motion.x, motion.z) {
+            motion.x = shrink(motion.x);
+            motion.z = shrink(motion.z);
+        }
+
+        motion
+    }
+
+    /// Retries a blocked horizontal move from up to `STEP_HEIGHT` higher, trying both the
+    /// "move up, across, down" and "rise in place" orders like vanilla does.
+    fn step_up(&self, world: &World, requested: Vec3) -> (Vec3, Aabb) {
+        let origin = self.bounding_box();
+        let boxes = self.collisions(
+            world,
+            origin.expand_towards(Vec3::new(requested.x, STEP_HEIGHT, requested.z)),
+        );
+
+        let attempt = |mut bb: Aabb, rise_area: Aabb| {
+            let rise = boxes
+                .iter()
+                .fold(STEP_HEIGHT, |y, shape| shape.clip_y(&rise_area, y));
+            bb = bb.offset(Vec3::new(0.0, rise, 0.0));
+
+            let x = boxes
+                .iter()
+                .fold(requested.x, |x, shape| shape.clip_x(&bb, x));
+            bb = bb.offset(Vec3::new(x, 0.0, 0.0));
+
+            let z = boxes
+                .iter()
+                .fold(requested.z, |z, shape| shape.clip_z(&bb, z));
+            bb = bb.offset(Vec3::new(0.0, 0.0, z));
+
+            let drop = boxes.iter().fold(-rise, |y, shape| shape.clip_y(&bb, y));
+            bb = bb.offset(Vec3::new(0.0, drop, 0.0));
+
+            (Vec3::new(x, drop, z), bb)
+        };
+
+        let swept = origin.expand_towards(Vec3::new(requested.x, 0.0, requested.z));
+        let (a, a_bb) = attempt(origin, swept);
+        let (b, b_bb) = attempt(origin, origin);
+
+        let horizontal = |v: Vec3| v.x * v.x + v.z * v.z;
+
+        match horizontal(a) > horizontal(b) {
+            true => (a, a_bb),
+            false => (b, b_bb),
+        }
+    }
+
+    /// Vanilla's `doBlockCollisions` for the blocks that alter movement when touched.
+    fn apply_block_effects(&mut self, world: &World) {
+        let area = self.bounding_box().inflate(-0.001, -0.001, -0.001);
+
+        for pos in Self::blocks_in(area) {
+            let Some(state) = world.block_at(pos.x, pos.y, pos.z) else {
+                continue;
+            };
+
+            if blocks::is_web(state) {
+                self.in_web = true;
+            }
+
+            if blocks::is_soul_sand(state) {
+                self.velocity.x *= 0.4;
+                self.velocity.z *= 0.4;
+            }
+        }
+    }
+}
+
+pub(crate) enum MovementUpdate {
+    PositionLook,
+    Position,
+    Look,
+    Flying,
+}
+
+/// Remembers what the server last heard about the bot so only the needed packet is sent,
+/// mirroring `EntityPlayerSP.onUpdateWalkingPlayer`.
+#[derive(Debug, Default)]
+pub(crate) struct MovementTracker {
+    last_position: Vec3,
+    last_yaw: f32,
+    last_pitch: f32,
+    ticks_since_position: u8,
+    pub sprinting: bool,
+    pub sneaking: bool,
+}
+
+impl MovementTracker {
+    const MIN_DISTANCE_SQUARED: f64 = 9.0E-4;
+    const FORCE_POSITION_TICKS: u8 = 20;
+
+    pub fn update(&mut self, physics: &PlayerPhysics) -> MovementUpdate {
+        // Counted before the check like vanilla, so a position is sent at least every 20 ticks
+        self.ticks_since_position += 1;
+
+        let moved = physics.position.distance_squared(self.last_position)
+            > Self::MIN_DISTANCE_SQUARED
+            || self.ticks_since_position >= Self::FORCE_POSITION_TICKS;
+        let rotated = physics.yaw != self.last_yaw || physics.pitch != self.last_pitch;
+
+        if moved {
+            self.last_position = physics.position;
+            self.ticks_since_position = 0;
+        }
+
+        if rotated {
+            self.last_yaw = physics.yaw;
+            self.last_pitch = physics.pitch;
+        }
+
+        match (moved, rotated) {
+            (true, true) => MovementUpdate::PositionLook,
+            (true, false) => MovementUpdate::Position,
+            (false, true) => MovementUpdate::Look,
+            (false, false) => MovementUpdate::Flying,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: u16 = 1;
+
+    fn floor(world: &mut World, y: i32) {
+        world.fill(
+            BlockPos::new(-8, y, -8),
+            BlockPos::new(8, y, 8),
+            BlockState::new(STONE, 0),
+        );
+    }
+
+    fn forward() -> Controls {
+        Controls {
+            forward: true,
+            ..Default::default()
+        }
+    }
+
+    /// Ticks `n` times and returns the position after each tick.
+    fn run(physics: &mut PlayerPhysics, world: &World, controls: Controls, n: usize) -> Vec<Vec3> {
+        (0..n)
+            .map(|_| {
+                physics.tick(world, &controls);
+                physics.position
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn falls_and_lands() {
+        let mut world = World::empty(1);
+        floor(&mut world, 63);
+
+        let mut physics = PlayerPhysics::new(Vec3::new(0.5, 65.0, 0.5), 0.0, 0.0);
+        let positions = run(&mut physics, &world, Controls::default(), 7);
+
+        // motionY = (motionY - 0.08) * 0.98 each tick, applied after moving
+        let expected = [
+            65.0,
+            64.92159999847412,
+            64.76636799395752,
+            64.53584062504456,
+            64.23152379758702,
+            64.0,
+            64.0,
+        ];
+
+        for (position, expected) in positions.iter().zip(expected) {
+            assert_close(position.y, expected, 1.0E-9);
+        }
+
+        assert!(physics.on_ground);
+        // Gravity keeps pulling while standing, which is what keeps vanilla players grounded
+        assert_close(physics.velocity.y, -0.0784, 1.0E-6);
+    }
+
+    #[test]
+    fn walks_at_vanilla_speed() {
+        let mut world = World::empty(1);
+        floor(&mut world, 63);
+
+        // Yaw -90 faces +x
+        let mut physics = PlayerPhysics::new(Vec3::new(-7.5, 64.0, 0.5), -90.0, 0.0);
+        let positions = run(&mut physics, &world, forward(), 40);
+
+        // 4.317 blocks per second
+        let last = positions[39].x - positions[38].x;
+        assert_close(last, 0.21585906, 1.0E-5);
+        assert_close(physics.position.z, 0.5, 1.0E-9);
+    }
+
+    #[test]
+    fn steps_up_onto_a_slab() {
+        let mut world = World::empty(1);
+        floor(&mut world, 63);
+        world.fill(
+            BlockPos::new(1, 64, -1),
+            BlockPos::new(7, 64, 1),
+            BlockState::new(blocks::STONE_SLAB, 0),
+        );
+
+        let mut physics = PlayerPhysics::new(Vec3::new(0.5, 64.0, 0.5), -90.0, 0.0);
+        let positions = run(&mut physics, &world, forward(), 20);
+
+        // Stepping is instant, never higher than the slab
+        assert!(positions.iter().all(|position| position.y <= 64.5 + 1.0E-9));
+        assert_close(physics.position.y, 64.5, 1.0E-9);
+        assert!(physics.on_ground);
+        assert!(physics.position.x > 1.3);
+    }
+
+    #[test]
+    fn sneaking_stops_at_the_edge() {
+        let mut world = World::empty(1);
+        world.set_block(0, 63, 0, BlockState::new(STONE, 0));
+
+        let controls = Controls {
+            sneak: true,
+            ..forward()
+        };
+
+        let mut physics = PlayerPhysics::new(Vec3::new(0.5, 64.0, 0.5), -90.0, 0.0);
+        run(&mut physics, &world, controls, 60);
+
+        // The bounding box is 0.6 wide, so its edge stays over the block below x = 1.3
+        assert!(physics.position.x > 1.2 && physics.position.x < 1.3);
+        assert_eq!(physics.position.y, 64.0);
+        assert!(physics.on_ground);
+    }
+
+    #[test]
+    fn climbs_ladders() {
+        let mut world = World::empty(1);
+        floor(&mut world, 63);
+        world.fill(
+            BlockPos::new(-1, 64, 0),
+            BlockPos::new(-1, 72, 0),
+            BlockState::new(STONE, 0),
+        );
+        // Metadata 5 hangs on the west side of its block
+        world.fill(
+            BlockPos::new(0, 64, 0),
+            BlockPos::new(0, 72, 0),
+            BlockState::new(blocks::LADDER, 5),
+        );
+
+        // Yaw 90 faces -x, into the ladder
+        let mut physics = PlayerPhysics::new(Vec3::new(0.5, 64.0, 0.5), 90.0, 0.0);
+        let positions = run(&mut physics, &world, forward(), 20);
+
+        // Pushing into a ladder sets motionY to 0.2, then gravity and drag apply
+        for pair in positions[15..].windows(2) {
+            assert_close(pair[1].y - pair[0].y, 0.11760000228881837, 1.0E-9);
+        }
+
+        assert_close(physics.position.x, 0.425, 1.0E-9);
+    }
+
+    #[test]
+    fn sinks_in_still_water() {
+        let mut world = World::empty(1);
+        floor(&mut world, 59);
+        world.fill(
+            BlockPos::new(-8, 60, -8),
+            BlockPos::new(8, 68, 8),
+            BlockState::new(blocks::STATIONARY_WATER, 0),
+        );
+
+        let mut physics = PlayerPhysics::new(Vec3::new(0.5, 65.0, 0.5), 0.0, 0.0);
+        let positions = run(&mut physics, &world, Controls::default(), 40);
+
+        assert!(physics.in_water);
+
+        // motionY = motionY * 0.8 - 0.02 converges to -0.1
+        assert_close(positions[39].y - positions[38].y, -0.1, 1.0E-3);
+        assert_eq!(physics.position.x, 0.5);
+        assert_eq!(physics.position.z, 0.5);
+    }
+
+    #[test]
+    fn flowing_water_pushes() {
+        let mut world = World::empty(1);
+        floor(&mut world, 63);
+
+        // A source at x = 0 spreading east, one level lower per block
+        for x in 0..8 {
+            world.set_block(x, 64, 0, BlockState::new(blocks::WATER, x as u8));
+        }
+
+        let mut physics = PlayerPhysics::new(Vec3::new(3.5, 64.0, 0.5), 0.0, 0.0);
+        run(&mut physics, &world, Controls::default(), 10);
+
+        assert!(physics.in_water);
+        assert!(physics.position.x > 3.5);
+        assert!(physics.velocity.x > 0.0);
+        assert_close(physics.position.z, 0.5, 1.0E-9);
+    }
+
+    #[test]
+    fn forces_a_position_every_20_ticks() {
+        let physics = PlayerPhysics::new(Vec3::ZERO, 0.0, 0.0);
+        let mut tracker = MovementTracker::default();
+
+        for tick in 1..=40 {
+            let update = tracker.update(&physics);
+
+            match tick % 20 {
+                0 => assert!(matches!(update, MovementUpdate::Position), "tick {tick}"),
+                _ => assert!(matches!(update, MovementUpdate::Flying), "tick {tick}"),
+            }
+        }
+    }
+}
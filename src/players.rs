@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use gami_mc_protocol::packets::play::server::PlayerInfo;
+
+const ADD_PLAYER: i32 = 0;
+const UPDATE_GAME_MODE: i32 = 1;
+const UPDATE_LATENCY: i32 = 2;
+const UPDATE_DISPLAY_NAME: i32 = 3;
+const REMOVE_PLAYER: i32 = 4;
+
+#[derive(Debug, Clone, Default)]
+pub struct PlayerListEntry {
+    pub uuid: String,
+    pub name: String,
+    pub game_mode: i32,
+    /// Latency in milliseconds, as measured by the server.
+    pub ping: i32,
+    /// A JSON chat component replacing `name` in the tab list.
+    pub display_name: Option<String>,
+}
+
+/// The players shown in the tab list, which includes players too far away to be entities.
+#[derive(Debug, Default)]
+pub struct PlayerList {
+    players: HashMap<String, PlayerListEntry>,
+}
+
+impl PlayerList {
+    pub fn get(&self, uuid: &str) -> Option<&PlayerListEntry> {
+        self.players.get(uuid)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&PlayerListEntry> {
+        self.players.values().find(|player| player.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PlayerListEntry> {
+        self.players.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    /// Returns the players that joined and the players that left.
+    pub(crate) fn apply(
+        &mut self,
+        data: &PlayerInfo,
+    ) -> (Vec<PlayerListEntry>, Vec<PlayerListEntry>) {
+        let mut joined = Vec::new();
+        let mut left = Vec::new();
+
+        for item in &data.data {
+            match data.action {
+                ADD_PLAYER => {
+                    let player = PlayerListEntry {
+                        uuid: item.uuid.clone(),
+                        name: item.name.clone().unwrap_or_default(),
+                        game_mode: item.gamemode.unwrap_or_default(),
+                        ping: item.ping.unwrap_or_default(),
+                        display_name: item.display_name.clone(),
+                    };
+
+                    // Also sent again for players already listed, e.g. after a skin change
+                    if self
+                        .players
+                        .insert(item.uuid.clone(), player.clone())
+                        .is_none()
+                    {
+                        joined.push(player);
+                    }
+                }
+
+                REMOVE_PLAYER => {
+                    if let Some(player) = self.players.remove(&item.uuid) {
+                        left.push(player);
+                    }
+                }
+
+                action => {
+                    let Some(player) = self.players.get_mut(&item.uuid) else {
+                        continue;
+                    };
+
+                    match action {
+                        UPDATE_GAME_MODE => player.game_mode = item.gamemode.unwrap_or_default(),
+                        UPDATE_LATENCY => player.ping = item.ping.unwrap_or_default(),
+                        UPDATE_DISPLAY_NAME => player.display_name = item.display_name.clone(),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        (joined, left)
+    }
+}
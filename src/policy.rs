@@ -0,0 +1,298 @@
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// What the bot does once the `Death` event handlers have run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeathPolicy {
+    #[default]
+    Respawn,
+    RespawnAfter(Duration),
+    /// Stays on the death screen until `Bot::respawn` is called.
+    Stay,
+    /// Leaves the server and stops the bot, without reconnecting.
+    Disconnect,
+}
+
+/// Returned when the server kicks the bot, while logging in or playing.
+#[derive(Debug, Clone)]
+pub struct Kicked {
+    /// The JSON chat component the server sent.
+    pub reason: String,
+}
+
+impl fmt::Display for Kicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Disconnected from server: {:?}", self.reason)
+    }
+}
+
+impl Error for Kicked {}
+
+#[derive(Debug, Clone)]
+pub enum DisconnectReason {
+    /// The JSON chat component the server kicked the bot with, e.g. for a ban.
+    Kicked(String),
+    /// The server couldn't be reached or the connection broke, with the error message.
+    ConnectionLost(String),
+}
+
+impl DisconnectReason {
+    pub(crate) fn from_error(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<Kicked>() {
+            Some(kicked) => Self::Kicked(kicked.reason.clone()),
+            None => Self::ConnectionLost(err.to_string()),
+        }
+    }
+}
+
+/// The connection was lost and the bot will reconnect after `delay`. Dispatched on the
+/// disconnected bot, so it can be inspected but packets sent through it are lost. Not
+/// dispatched before the first successful connection.
+#[derive(Debug, Clone)]
+pub struct Reconnecting {
+    pub reason: DisconnectReason,
+    /// Starts at 1, reset once a session is stable.
+    pub attempt: u32,
+    pub delay: Duration,
+}
+
+/// The bot joined the server again, dispatched on the new bot.
+#[derive(Debug, Clone)]
+pub struct Reconnected {
+    /// Attempts made since the last stable session, including this one.
+    pub attempts: u32,
+}
+
+/// When and how often the bot reconnects after losing its connection.
+///
+/// The delay starts at `initial_delay` and is multiplied after every failed attempt, up to
+/// `max_delay`. Attempts are counted until a session lasts long enough to be considered
+/// stable, so a server kicking the bot right after joining isn't retried at full speed.
+#[derive(Clone)]
+pub struct ReconnectPolicy {
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: f64,
+    max_attempts: Option<u32>,
+    stable_after: Duration,
+    predicate: Option<Rc<dyn Fn(&DisconnectReason) -> bool>>,
+}
+
+impl Default for ReconnectPolicy {
+    /// Retries forever, from 5 seconds up to 2 minutes apart.
+    fn default() -> Self {
+        Self::exponential(Duration::from_secs(5), Duration::from_secs(120))
+    }
+}
+
+impl ReconnectPolicy {
+    /// Doubles the delay after every attempt, with up to 25% jitter.
+    pub fn exponential(initial_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            initial_delay,
+            max_delay,
+            multiplier: 2.0,
+            jitter: 0.25,
+            max_attempts: None,
+            stable_after: Duration::from_secs(60),
+            predicate: None,
+        }
+    }
+
+    /// Always waits `delay`, without jitter.
+    pub fn fixed(delay: Duration) -> Self {
+        Self::exponential(delay, delay)
+            .with_multiplier(1.0)
+            .with_jitter(0.0)
+    }
+
+    /// `BotBuilder::run` returns the error instead of reconnecting.
+    pub fn never() -> Self {
+        Self::default().with_max_attempts(0)
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Removes up to this fraction of each delay at random, so bots disconnected together
+    /// don't all reconnect at the same time. Clamped between 0 and 1.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Gives up after `attempts` reconnects in a row without a stable session.
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Sessions lasting at least `duration` reset the attempt count. Defaults to 1 minute.
+    pub fn reset_after(mut self, duration: Duration) -> Self {
+        self.stable_after = duration;
+        self
+    }
+
+    /// Only reconnects when `predicate` returns `true`, e.g. to stop after a ban.
+    pub fn retry_if(mut self, predicate: impl Fn(&DisconnectReason) -> bool + 'static) -> Self {
+        self.predicate = Some(Rc::new(predicate));
+        self
+    }
+
+    /// The delay before the given attempt, starting at 1, without jitter.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        // Kept finite so a zero initial delay stays zero instead of becoming NaN
+        let factor = self.multiplier.powi(exponent).min(f64::MAX);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+
+        // Too large for a `Duration` once the multiplier has been applied enough times
+        let delay = Duration::try_from_secs_f64(delay).unwrap_or(Duration::MAX);
+        delay.min(self.max_delay)
+    }
+
+    pub(crate) fn stable_after(&self) -> Duration {
+        self.stable_after
+    }
+
+    /// The jittered delay before the given attempt, `None` to give up.
+    pub(crate) fn next_delay(&self, attempt: u32, reason: &DisconnectReason) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt > max) {
+            return None;
+        }
+
+        if let Some(ref predicate) = self.predicate {
+            if !predicate(reason) {
+                return None;
+            }
+        }
+
+        let delay = self.delay(attempt);
+        let jitter = self.jitter * rand::random::<f64>();
+
+        // `mul_f64` would panic on a delay close to `Duration::MAX`, which f64 rounds up
+        let jittered = delay.as_secs_f64() * (1.0 - jitter);
+        Some(Duration::try_from_secs_f64(jittered).map_or(delay, |jittered| jittered.min(delay)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lost() -> DisconnectReason {
+        DisconnectReason::ConnectionLost("Connection reset by peer".to_string())
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn exponential_delays() {
+        let policy = ReconnectPolicy::exponential(secs(1), secs(30));
+        let delays: Vec<_> = (1..=7).map(|attempt| policy.delay(attempt)).collect();
+
+        assert_eq!(
+            delays,
+            [
+                secs(1),
+                secs(2),
+                secs(4),
+                secs(8),
+                secs(16),
+                secs(30),
+                secs(30)
+            ]
+        );
+
+        // Attempt 0 is treated as the first one
+        assert_eq!(policy.delay(0), secs(1));
+    }
+
+    #[test]
+    fn huge_delays_do_not_overflow() {
+        let policy = ReconnectPolicy::exponential(secs(5), Duration::MAX);
+
+        assert_eq!(policy.delay(u32::MAX), Duration::MAX);
+        assert_eq!(
+            policy.next_delay(2000, &lost()).unwrap(),
+            policy.delay(2000)
+        );
+        assert!(policy.next_delay(u32::MAX, &lost()).unwrap() <= Duration::MAX);
+
+        let policy = ReconnectPolicy::exponential(Duration::ZERO, Duration::MAX)
+            .with_multiplier(f64::INFINITY);
+        assert_eq!(policy.delay(3), Duration::ZERO);
+
+        let policy = ReconnectPolicy::fixed(Duration::MAX);
+        assert_eq!(policy.next_delay(1, &lost()), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn fixed_delays() {
+        let policy = ReconnectPolicy::fixed(secs(3));
+
+        for attempt in [1, 2, 10, 1000] {
+            assert_eq!(policy.delay(attempt), secs(3));
+            assert_eq!(policy.next_delay(attempt, &lost()), Some(secs(3)));
+        }
+    }
+
+    #[test]
+    fn never_reconnects() {
+        let policy = ReconnectPolicy::never();
+
+        assert_eq!(policy.next_delay(1, &lost()), None);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let policy = ReconnectPolicy::fixed(secs(1)).with_max_attempts(3);
+
+        assert!(policy.next_delay(1, &lost()).is_some());
+        assert!(policy.next_delay(3, &lost()).is_some());
+        assert!(policy.next_delay(4, &lost()).is_none());
+    }
+
+    #[test]
+    fn stable_sessions() {
+        assert_eq!(ReconnectPolicy::default().stable_after(), secs(60));
+
+        let policy = ReconnectPolicy::default().reset_after(secs(10));
+        assert_eq!(policy.stable_after(), secs(10));
+    }
+
+    #[test]
+    fn jitter_stays_in_bounds() {
+        let policy = ReconnectPolicy::exponential(secs(8), secs(8)).with_jitter(0.5);
+
+        for _ in 0..1000 {
+            let delay = policy.next_delay(1, &lost()).unwrap();
+            assert!(delay > secs(4) && delay <= secs(8), "{delay:?}");
+        }
+
+        // Clamped so the delay never goes negative
+        let policy = ReconnectPolicy::exponential(secs(8), secs(8)).with_jitter(3.0);
+
+        for _ in 0..100 {
+            assert!(policy.next_delay(1, &lost()).unwrap() <= secs(8));
+        }
+    }
+
+    #[test]
+    fn retries_only_if_the_predicate_allows() {
+        let policy = ReconnectPolicy::fixed(secs(1))
+            .retry_if(|reason| matches!(reason, DisconnectReason::ConnectionLost(_)));
+
+        assert!(policy.next_delay(1, &lost()).is_some());
+
+        let banned = DisconnectReason::Kicked(r#"{"text":"You are banned"}"#.to_string());
+        assert!(policy.next_delay(1, &banned).is_none());
+    }
+}
@@ -0,0 +1,443 @@
+use std::fmt;
+use std::net::IpAddr;
+
+use anyhow::{bail, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const SOCKS_VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHOD: u8 = 0xFF;
+const CONNECT: u8 = 0x01;
+const IPV4: u8 = 0x01;
+const DOMAIN: u8 = 0x03;
+const IPV6: u8 = 0x04;
+/// Proxies answering with larger headers are most likely not HTTP proxies.
+const MAX_HTTP_HEADER: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    Socks5,
+    /// Tunnels through `CONNECT` requests.
+    Http,
+}
+
+/// A proxy the bot's connection is tunneled through, e.g. to spread bots over several IPs.
+#[derive(Clone)]
+pub struct Proxy {
+    kind: ProxyKind,
+    addr: String,
+    credentials: Option<(String, String)>,
+}
+
+impl fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Kept out of logs, providers often put session tokens in the username as well
+        let credentials = self.credentials.as_ref().map(|_| "<redacted>");
+
+        f.debug_struct("Proxy")
+            .field("kind", &self.kind)
+            .field("addr", &self.addr)
+            .field("credentials", &credentials)
+            .finish()
+    }
+}
+
+impl Proxy {
+    /// `addr` is the proxy's `host:port`.
+    pub fn socks5(addr: impl ToString) -> Self {
+        Self {
+            kind: ProxyKind::Socks5,
+            addr: addr.to_string(),
+            credentials: None,
+        }
+    }
+
+    pub fn http(addr: impl ToString) -> Self {
+        Self {
+            kind: ProxyKind::Http,
+            addr: addr.to_string(),
+            credentials: None,
+        }
+    }
+
+    pub fn with_credentials(mut self, username: impl ToString, password: impl ToString) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    pub fn kind(&self) -> ProxyKind {
+        self.kind
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Opens a tunnel to `host:port`, the proxy resolves the host.
+    pub async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let mut stream = TcpStream::connect(&self.addr).await?;
+        stream.set_nodelay(true)?;
+
+        match self.kind {
+            ProxyKind::Socks5 => self.socks5_handshake(&mut stream, host, port).await?,
+            ProxyKind::Http => self.http_handshake(&mut stream, host, port).await?,
+        }
+
+        Ok(stream)
+    }
+
+    async fn socks5_handshake(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+        // Proxies may still let us in without the credentials, so offer both methods
+        let greeting: &[u8] = match self.credentials {
+            Some(_) => &[SOCKS_VERSION, 2, NO_AUTH, USERNAME_PASSWORD],
+            None => &[SOCKS_VERSION, 1, NO_AUTH],
+        };
+
+        stream.write_all(greeting).await?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply).await?;
+
+        if reply[0] != SOCKS_VERSION {
+            bail!("Proxy at {} isn't a SOCKS5 proxy", self.addr);
+        }
+
+        match reply[1] {
+            NO_AUTH => {}
+            USERNAME_PASSWORD => self.socks5_authenticate(stream).await?,
+            NO_ACCEPTABLE_METHOD => bail!("SOCKS5 proxy refused the authentication method"),
+            method => bail!("SOCKS5 proxy selected an unknown method {method:#04x}"),
+        }
+
+        let mut request = vec![SOCKS_VERSION, CONNECT, 0x00];
+
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(IPV4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(IPV6);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                let Ok(length) = u8::try_from(host.len()) else {
+                    bail!("Host name is too long for SOCKS5: {host}");
+                };
+
+                request.push(DOMAIN);
+                request.push(length);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply).await?;
+
+        if reply[1] != 0x00 {
+            bail!(
+                "SOCKS5 proxy couldn't connect to {host}:{port}: {}",
+                socks5_error(reply[1])
+            );
+        }
+
+        // The address the proxy bound to, which the tunnel doesn't need
+        let length = match reply[3] {
+            IPV4 => 4,
+            IPV6 => 16,
+            DOMAIN => stream.read_u8().await? as usize,
+            kind => bail!("SOCKS5 proxy replied with an unknown address type {kind:#04x}"),
+        };
+
+        let mut bound = vec![0; length + 2];
+        stream.read_exact(&mut bound).await?;
+
+        Ok(())
+    }
+
+    async fn socks5_authenticate(&self, stream: &mut TcpStream) -> Result<()> {
+        const AUTH_VERSION: u8 = 0x01;
+
+        let Some((ref username, ref password)) = self.credentials else {
+            bail!("SOCKS5 proxy asked for credentials, none were given");
+        };
+
+        let (Ok(username_length), Ok(password_length)) =
+            (u8::try_from(username.len()), u8::try_from(password.len()))
+        else {
+            bail!("SOCKS5 credentials can't be longer than 255 bytes");
+        };
+
+        let mut request = vec![AUTH_VERSION, username_length];
+        request.extend_from_slice(username.as_bytes());
+        request.push(password_length);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply).await?;
+
+        if reply[1] != 0x00 {
+            bail!("SOCKS5 proxy rejected the credentials");
+        }
+
+        Ok(())
+    }
+
+    async fn http_handshake(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+        let authority = match host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(_)) => format!("[{host}]:{port}"),
+            _ => format!("{host}:{port}"),
+        };
+
+        let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+
+        if let Some((ref username, ref password)) = self.credentials {
+            let token = STANDARD.encode(format!("{username}:{password}"));
+            request.push_str(&format!("Proxy-Authorization: Basic {token}\r\n"));
+        }
+
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read byte by byte so nothing sent by the server through the tunnel is consumed
+        let mut response = Vec::new();
+
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() > MAX_HTTP_HEADER {
+                bail!("HTTP proxy sent an oversized response");
+            }
+
+            response.push(stream.read_u8().await?);
+        }
+
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        let status = status_line.split_whitespace().nth(1);
+
+        if !status.is_some_and(|status| status.starts_with('2')) {
+            bail!("HTTP proxy couldn't connect to {authority}: {status_line}");
+        }
+
+        Ok(())
+    }
+}
+
+fn socks5_error(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    use super::*;
+
+    /// Accepts a single connection and runs `script` on it, playing the proxy's side.
+    async fn stand_in<F, Fut>(script: F) -> (String, JoinHandle<Fut::Output>)
+    where
+        F: FnOnce(TcpStream) -> Fut + Send + 'static,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let task = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            script(stream).await
+        });
+
+        (addr, task)
+    }
+
+    async fn read_vec(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        stream.read_exact(&mut buf).await.unwrap();
+        buf
+    }
+
+    async fn read_http_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+
+        String::from_utf8(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn socks5_without_credentials() {
+        let (addr, proxy) = stand_in(|mut stream| async move {
+            let greeting = read_vec(&mut stream, 3).await;
+            stream.write_all(&[5, NO_AUTH]).await.unwrap();
+
+            let mut request = read_vec(&mut stream, 5).await;
+            let rest = request[4] as usize + 2;
+            request.extend(read_vec(&mut stream, rest).await);
+
+            stream
+                .write_all(&[5, 0, 0, IPV4, 127, 0, 0, 1, 0x1F, 0x90])
+                .await
+                .unwrap();
+            stream.write_all(b"hello").await.unwrap();
+
+            (greeting, request)
+        })
+        .await;
+
+        let mut stream = Proxy::socks5(addr)
+            .connect("mc.example.net", 25565)
+            .await
+            .unwrap();
+
+        assert_eq!(read_vec(&mut stream, 5).await, b"hello");
+
+        let (greeting, request) = proxy.await.unwrap();
+        assert_eq!(greeting, [5, 1, NO_AUTH]);
+
+        let mut expected = vec![5, CONNECT, 0, DOMAIN, 14];
+        expected.extend_from_slice(b"mc.example.net");
+        expected.extend_from_slice(&25565u16.to_be_bytes());
+        assert_eq!(request, expected);
+    }
+
+    #[tokio::test]
+    async fn socks5_with_credentials() {
+        let (addr, proxy) = stand_in(|mut stream| async move {
+            let greeting = read_vec(&mut stream, 4).await;
+            stream.write_all(&[5, USERNAME_PASSWORD]).await.unwrap();
+
+            let auth = read_vec(&mut stream, 13).await;
+            stream.write_all(&[1, 0]).await.unwrap();
+
+            let request = read_vec(&mut stream, 10).await;
+
+            // Bound to a domain, which the client has to skip
+            stream
+                .write_all(&[5, 0, 0, DOMAIN, 4, b'a', b'b', b'c', b'd', 0x1F, 0x90])
+                .await
+                .unwrap();
+            stream.write_all(b"hello").await.unwrap();
+
+            (greeting, auth, request)
+        })
+        .await;
+
+        let mut stream = Proxy::socks5(addr)
+            .with_credentials("user", "secret")
+            .connect("10.0.0.1", 25566)
+            .await
+            .unwrap();
+
+        assert_eq!(read_vec(&mut stream, 5).await, b"hello");
+
+        let (greeting, auth, request) = proxy.await.unwrap();
+        assert_eq!(greeting, [5, 2, NO_AUTH, USERNAME_PASSWORD]);
+        assert_eq!(auth, b"\x01\x04user\x06secret");
+        assert_eq!(request, [5, CONNECT, 0, IPV4, 10, 0, 0, 1, 0x63, 0xDE]);
+    }
+
+    #[tokio::test]
+    async fn socks5_rejected_credentials() {
+        let (addr, _proxy) = stand_in(|mut stream| async move {
+            read_vec(&mut stream, 4).await;
+            stream.write_all(&[5, USERNAME_PASSWORD]).await.unwrap();
+            read_vec(&mut stream, 12).await;
+            stream.write_all(&[1, 1]).await.unwrap();
+        })
+        .await;
+
+        let result = Proxy::socks5(addr)
+            .with_credentials("user", "wrong")
+            .connect("mc.example.net", 25565)
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn http_keeps_bytes_sent_after_the_response() {
+        let (addr, proxy) = stand_in(|mut stream| async move {
+            let request = read_http_request(&mut stream).await;
+
+            // In one write, so the server's first bytes arrive with the response
+            stream
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n\x01\x02\x03")
+                .await
+                .unwrap();
+
+            request
+        })
+        .await;
+
+        let mut stream = Proxy::http(addr)
+            .with_credentials("user", "pass")
+            .connect("mc.example.net", 25565)
+            .await
+            .unwrap();
+
+        assert_eq!(read_vec(&mut stream, 3).await, [1, 2, 3]);
+
+        let request = proxy.await.unwrap();
+        assert!(request.starts_with("CONNECT mc.example.net:25565 HTTP/1.1\r\n"));
+        assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+    }
+
+    #[tokio::test]
+    async fn http_errors() {
+        for response in [
+            "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic\r\n\r\n",
+            "HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n",
+        ] {
+            let (addr, _proxy) = stand_in(move |mut stream| async move {
+                read_http_request(&mut stream).await;
+                stream.write_all(response.as_bytes()).await.unwrap();
+            })
+            .await;
+
+            let error = Proxy::http(addr)
+                .connect("mc.example.net", 25565)
+                .await
+                .unwrap_err();
+
+            let status = &response[9..12];
+            assert!(error.to_string().contains(status), "{error}");
+        }
+    }
+
+    #[test]
+    fn debug_redacts_credentials() {
+        let proxy =
+            Proxy::socks5("127.0.0.1:1080").with_credentials("user-session-1234", "hunter2");
+        let debug = format!("{proxy:?}");
+
+        assert!(debug.contains("127.0.0.1:1080"));
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains("user-session-1234"));
+        assert!(!debug.contains("hunter2"));
+
+        assert!(format!("{:?}", Proxy::http("127.0.0.1:8080")).contains("credentials: None"));
+    }
+}
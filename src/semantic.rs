@@ -0,0 +1,88 @@
+use std::rc::Rc;
+
+use gami_mc_protocol::registry::{Dimension, EntityKind};
+
+use crate::entity::Transform;
+use crate::inventory::Inventory;
+use crate::math::Vec3;
+use crate::players::PlayerListEntry;
+
+// Payloads of the high-level events the bot derives from packets. They're dispatched once
+// the bot has applied the packet, so `ctx.bot` already reflects the change.
+
+/// The bot's health reached 0.
+#[derive(Debug, Clone)]
+pub struct Death {
+    /// Where the bot died, `None` if it never spawned.
+    pub position: Option<Vec3>,
+    /// The JSON chat component announcing the death, if the server sent one.
+    pub message: Option<String>,
+    /// The inventory right before dying, which is what got dropped unless the server keeps
+    /// inventories.
+    pub inventory: Inventory,
+}
+
+/// The bot got its first position after joining or respawning.
+#[derive(Debug, Clone)]
+pub struct Spawn {
+    pub position: Vec3,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthChange {
+    pub previous: f32,
+    pub health: f32,
+    pub food: i32,
+    pub saturation: f32,
+}
+
+/// An entity started being tracked, see `World::entities`.
+#[derive(Debug, Clone)]
+pub struct EntitySpawn {
+    pub entity_id: i32,
+    pub transform: Transform,
+}
+
+/// An entity stopped being tracked, it's no longer in the world.
+#[derive(Debug, Clone)]
+pub struct EntityDespawn {
+    pub entity: Rc<EntityKind>,
+    pub transform: Transform,
+}
+
+#[derive(Debug, Clone)]
+pub struct EntityMove {
+    pub entity_id: i32,
+    pub previous: Transform,
+    pub transform: Transform,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerJoin {
+    pub player: PlayerListEntry,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerLeave {
+    pub player: PlayerListEntry,
+}
+
+/// An entity picked up an item, arrow or experience orb. The collected entity is still in
+/// the world until the server destroys it.
+#[derive(Debug, Clone)]
+pub struct ItemPickup {
+    pub collected_id: i32,
+    pub collector_id: i32,
+}
+
+/// An entity, possibly the bot, took damage.
+#[derive(Debug, Clone)]
+pub struct Hurt {
+    pub entity_id: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct DimensionChange {
+    pub previous: Dimension,
+    pub dimension: Dimension,
+}
@@ -0,0 +1,139 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+
+const MOJANG_SESSION_SERVER: &str = "https://sessionserver.mojang.com";
+
+/// An authenticated Minecraft profile, required to join online-mode servers.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub username: String,
+    /// The profile UUID without dashes.
+    pub uuid: String,
+    pub access_token: String,
+}
+
+impl Session {
+    /// Offline accounts have no access token.
+    pub fn is_offline(&self) -> bool {
+        self.access_token.is_empty()
+    }
+}
+
+/// Tells the session server that a profile is joining a server, so the server can verify it.
+#[async_trait]
+pub trait SessionService: Send + Sync {
+    async fn join(&self, session: &Session, server_hash: &str) -> Result<()>;
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JoinRequest<'a> {
+    access_token: &'a str,
+    selected_profile: &'a str,
+    server_id: &'a str,
+}
+
+pub struct MojangSessionService {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl MojangSessionService {
+    /// Uses another session server, e.g. a local mock.
+    pub fn new(url: impl ToString) -> Self {
+        Self {
+            url: url.to_string().trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl Default for MojangSessionService {
+    fn default() -> Self {
+        Self::new(MOJANG_SESSION_SERVER)
+    }
+}
+
+#[async_trait]
+impl SessionService for MojangSessionService {
+    async fn join(&self, session: &Session, server_hash: &str) -> Result<()> {
+        let request = JoinRequest {
+            access_token: &session.access_token,
+            selected_profile: &session.uuid,
+            server_id: server_hash,
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/session/minecraft/join", self.url))
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("Session server refused the join ({status}): {body}");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::test_server::TestServer;
+
+    fn session() -> Session {
+        Session {
+            username: "Steve".to_string(),
+            uuid: "069a79f444e94726a5befca90e38aaf5".to_string(),
+            access_token: "token".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn joins() {
+        let server = TestServer::start(|_| (204, String::new())).await;
+
+        MojangSessionService::new(format!("{}/", server.url))
+            .join(&session(), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1")
+            .await
+            .unwrap();
+
+        let requests = server.requests_to("/session/minecraft/join");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+
+        let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "accessToken": "token",
+                "selectedProfile": "069a79f444e94726a5befca90e38aaf5",
+                "serverId": "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_refused_joins() {
+        let server = TestServer::start(|_| {
+            let error = json!({ "error": "ForbiddenOperationException" });
+            (403, error.to_string())
+        })
+        .await;
+
+        let err = MojangSessionService::new(&server.url)
+            .join(&session(), "hash")
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("403"));
+        assert!(err.to_string().contains("ForbiddenOperationException"));
+    }
+}
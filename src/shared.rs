@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+
+use gami_mc_protocol::registry::Dimension;
+
+use crate::chunk::Chunk;
+
+pub(crate) type ChunkMap = HashMap<(i32, i32), ChunkEntry>;
+
+pub(crate) struct ChunkEntry {
+    pub chunk: Chunk,
+    /// Number of worlds that received this chunk from the server and haven't unloaded it yet.
+    pub viewers: usize,
+    /// Whether the chunk changed since it was read from or written to the disk cache.
+    pub dirty: bool,
+}
+
+impl ChunkEntry {
+    pub fn new(chunk: Chunk) -> Self {
+        Self {
+            chunk,
+            viewers: 0,
+            dirty: true,
+        }
+    }
+}
+
+/// A chunk store that can be read and fed by several worlds at once.
+#[derive(Clone, Default)]
+pub struct SharedChunks(Arc<RwLock<ChunkMap>>);
+
+impl SharedChunks {
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    pub fn contains(&self, cx: i32, cz: i32) -> bool {
+        self.read().contains_key(&(cx, cz))
+    }
+
+    /// Returns how many worlds currently keep the chunk loaded.
+    pub fn viewers(&self, cx: i32, cz: i32) -> usize {
+        self.read().get(&(cx, cz)).map_or(0, |entry| entry.viewers)
+    }
+
+    // A handler panicking on one bot must not take down every other bot sharing the store
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, ChunkMap> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, ChunkMap> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn downgrade(&self) -> Weak<RwLock<ChunkMap>> {
+        Arc::downgrade(&self.0)
+    }
+}
+
+/// Hands out one chunk store per server and dimension so bots connected to the same
+/// world keep a single copy of every chunk in memory.
+///
+/// Entities stay per bot: relative moves are deltas and applying the copy received by
+/// every bot would move them several times. Block updates are reported to every bot, but
+/// only the first one to apply them sees the old state, the others get `old == new`.
+#[derive(Clone, Default)]
+pub struct SharedWorlds {
+    worlds: Arc<Mutex<HashMap<(String, Dimension), Weak<RwLock<ChunkMap>>>>>,
+}
+
+impl SharedWorlds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, server: &str, dimension: Dimension) -> SharedChunks {
+        let mut worlds = self.worlds.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Stores are dropped with the last world using them
+        worlds.retain(|_, store| store.strong_count() > 0);
+
+        let key = (server.to_string(), dimension);
+
+        if let Some(store) = worlds.get(&key).and_then(Weak::upgrade) {
+            return SharedChunks(store);
+        }
+
+        let chunks = SharedChunks::default();
+        worlds.insert(key, chunks.downgrade());
+
+        chunks
+    }
+}
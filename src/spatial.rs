@@ -0,0 +1,133 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::math::{Aabb, Vec3};
+
+const CELL_SIZE: f64 = 16.0;
+
+type Cell = (i32, i32, i32);
+
+/// Buckets entity ids into 16 block cubes so area queries only look at nearby entities.
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    cells: HashMap<Cell, HashSet<i32>>,
+    entities: HashMap<i32, Cell>,
+}
+
+impl SpatialIndex {
+    fn cell(position: Vec3) -> Cell {
+        (
+            (position.x / CELL_SIZE).floor() as i32,
+            (position.y / CELL_SIZE).floor() as i32,
+            (position.z / CELL_SIZE).floor() as i32,
+        )
+    }
+
+    pub fn insert(&mut self, id: i32, position: Vec3) {
+        let cell = Self::cell(position);
+
+        match self.entities.insert(id, cell) {
+            Some(previous) if previous == cell => return,
+            Some(previous) => self.remove_from_cell(id, previous),
+            None => {}
+        }
+
+        self.cells.entry(cell).or_default().insert(id);
+    }
+
+    pub fn remove(&mut self, id: i32) {
+        if let Some(cell) = self.entities.remove(&id) {
+            self.remove_from_cell(id, cell);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.entities.clear();
+    }
+
+    fn remove_from_cell(&mut self, id: i32, cell: Cell) {
+        if let Some(ids) = self.cells.get_mut(&cell) {
+            ids.remove(&id);
+
+            if ids.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    /// Returns the ids of every entity in a cell touching the area, callers still need to
+    /// check the exact positions.
+    pub fn candidates(&self, area: &Aabb) -> Box<dyn Iterator<Item = i32> + '_> {
+        // Float to int casts saturate, so huge or infinite areas are clamped to the i32 range
+        let min = Self::cell(area.min);
+        let max = Self::cell(area.max);
+
+        let cell_count = [(min.0, max.0), (min.1, max.1), (min.2, max.2)]
+            .into_iter()
+            .map(|(min, max)| (max as i64 - min as i64 + 1).max(0) as u64)
+            .fold(1u64, u64::saturating_mul);
+
+        // Large areas would visit mostly empty cells, checking every entity is cheaper
+        if cell_count > self.entities.len() as u64 {
+            let contains = move |cell: &Cell| {
+                (min.0..=max.0).contains(&cell.0)
+                    && (min.1..=max.1).contains(&cell.1)
+                    && (min.2..=max.2).contains(&cell.2)
+            };
+
+            return Box::new(
+                self.entities
+                    .iter()
+                    .filter(move |(_, cell)| contains(cell))
+                    .map(|(&id, _)| id),
+            );
+        }
+
+        Box::new(
+            (min.0..=max.0)
+                .flat_map(move |x| (min.1..=max.1).map(move |y| (x, y)))
+                .flat_map(move |(x, y)| (min.2..=max.2).map(move |z| (x, y, z)))
+                .filter_map(|cell| self.cells.get(&cell))
+                .flatten()
+                .copied(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(ids: impl Iterator<Item = i32>) -> Vec<i32> {
+        let mut ids: Vec<_> = ids.collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn finds_nearby_entities() {
+        let mut index = SpatialIndex::default();
+        index.insert(1, Vec3::new(0.0, 64.0, 0.0));
+        index.insert(2, Vec3::new(8.0, 64.0, 8.0));
+
+        // Enough entities elsewhere for the query to go through the cells
+        for id in 10..20 {
+            index.insert(id, Vec3::new(500.0 + id as f64, 64.0, 500.0));
+        }
+
+        let ids = sorted(index.candidates(&Aabb::around(Vec3::new(0.0, 64.0, 0.0), 4.0)));
+        assert_eq!(ids, [1, 2]);
+    }
+
+    #[test]
+    fn handles_huge_and_infinite_areas() {
+        let mut index = SpatialIndex::default();
+        index.insert(1, Vec3::new(0.0, 64.0, 0.0));
+        index.insert(2, Vec3::new(-1e9, 64.0, 1e9));
+
+        for radius in [1e12, f64::MAX, f64::INFINITY] {
+            let ids = sorted(index.candidates(&Aabb::around(Vec3::new(0.0, 64.0, 0.0), radius)));
+            assert_eq!(ids, [1, 2]);
+        }
+    }
+}
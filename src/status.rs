@@ -0,0 +1,378 @@
+use std::io::Cursor;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use gami_mc_protocol::packets::{self, Packet};
+use gami_mc_protocol::registry::tcp::State;
+use gami_mc_protocol::serialization::encoding::VarIntReader;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::proxy::Proxy;
+use crate::stream::PROTOCOL_VERSION;
+
+const STATUS_REQUEST: i32 = 0x00;
+const PING: i32 = 0x01;
+/// Status responses are capped at 32767 characters of up to 4 bytes each, plus framing.
+const MAX_PACKET_LENGTH: usize = 32767 * 4 + 8;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a server shows in the multiplayer server list.
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    pub version: ServerVersion,
+    pub players: ServerPlayers,
+    /// The MOTD as a chat component, which can be a plain string.
+    pub description: Value,
+    /// A `data:image/png;base64,...` URI.
+    pub favicon: Option<String>,
+    /// Round trip time of the ping packet.
+    pub latency: Duration,
+}
+
+impl ServerStatus {
+    /// The MOTD without any formatting.
+    pub fn motd(&self) -> String {
+        strip_formatting(&plain_text(&self.description))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerVersion {
+    pub name: String,
+    pub protocol: i32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServerPlayers {
+    pub max: i32,
+    pub online: i32,
+    /// Some of the online players, servers often fill it with custom lines instead.
+    #[serde(default)]
+    pub sample: Vec<PlayerSample>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+struct StatusResponse {
+    version: ServerVersion,
+    #[serde(default)]
+    players: ServerPlayers,
+    #[serde(default)]
+    description: Value,
+    favicon: Option<String>,
+}
+
+/// Queries a server's status the way the server list does, without logging in.
+pub async fn ping(host: &str, port: u16) -> Result<ServerStatus> {
+    let query = async {
+        let mut stream = TcpStream::connect((host, port)).await?;
+        stream.set_nodelay(true)?;
+        query(stream, host, port).await
+    };
+
+    match tokio::time::timeout(TIMEOUT, query).await {
+        Ok(status) => status,
+        Err(_) => bail!("Timed out pinging {host}:{port}"),
+    }
+}
+
+/// Same as `ping`, tunneled through `proxy`.
+pub async fn ping_through(proxy: &Proxy, host: &str, port: u16) -> Result<ServerStatus> {
+    let query = async {
+        let stream = proxy.connect(host, port).await?;
+        query(stream, host, port).await
+    };
+
+    match tokio::time::timeout(TIMEOUT, query).await {
+        Ok(status) => status,
+        Err(_) => bail!("Timed out pinging {host}:{port} through {}", proxy.addr()),
+    }
+}
+
+async fn query(mut stream: TcpStream, host: &str, port: u16) -> Result<ServerStatus> {
+    let handshake = packets::handshake::client::SetProtocol {
+        protocol_version: PROTOCOL_VERSION,
+        server_host: host.to_string(),
+        server_port: port,
+        next_state: State::Status,
+    };
+
+    stream.write_all(&handshake.serialize(-1)?).await?;
+    write_packet(&mut stream, STATUS_REQUEST, &[]).await?;
+
+    let body = read_packet(&mut stream, STATUS_REQUEST).await?;
+    let mut cursor = Cursor::new(body.as_slice());
+    let length = cursor.read_varint()? as usize;
+    let start = cursor.position() as usize;
+
+    let Some(json) = body[start..].get(..length) else {
+        bail!("Status response is shorter than its declared length");
+    };
+
+    let response: StatusResponse = serde_json::from_slice(json)?;
+
+    // Any value works, vanilla sends the current time
+    let payload = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let sent = Instant::now();
+    write_packet(&mut stream, PING, &payload.to_be_bytes()).await?;
+
+    let pong = read_packet(&mut stream, PING).await?;
+    let latency = sent.elapsed();
+
+    if pong != payload.to_be_bytes() {
+        bail!("Server answered the ping with another payload");
+    }
+
+    Ok(ServerStatus {
+        version: response.version,
+        players: response.players,
+        description: response.description,
+        favicon: response.favicon,
+        latency,
+    })
+}
+
+/// Writes an uncompressed packet, compression is never enabled in the status state.
+async fn write_packet(stream: &mut TcpStream, id: i32, body: &[u8]) -> Result<()> {
+    let mut packet = Vec::with_capacity(body.len() + 1);
+    write_varint(&mut packet, id);
+    packet.extend_from_slice(body);
+
+    let mut frame = Vec::with_capacity(packet.len() + 5);
+    write_varint(&mut frame, packet.len() as i32);
+    frame.extend_from_slice(&packet);
+
+    stream.write_all(&frame).await?;
+
+    Ok(())
+}
+
+/// Reads the next packet and returns its body, failing if it isn't the expected one.
+async fn read_packet(stream: &mut TcpStream, expected_id: i32) -> Result<Vec<u8>> {
+    let length = read_varint(stream).await? as usize;
+
+    if length > MAX_PACKET_LENGTH {
+        bail!("Status packet is too long ({length} bytes)");
+    }
+
+    let mut packet = vec![0; length];
+    stream.read_exact(&mut packet).await?;
+
+    let mut cursor = Cursor::new(packet.as_slice());
+    let id = cursor.read_varint()?;
+
+    if id != expected_id {
+        bail!("Expected status packet {expected_id:#04x}, got {id:#04x}");
+    }
+
+    let start = cursor.position() as usize;
+    Ok(packet[start..].to_vec())
+}
+
+async fn read_varint(stream: &mut (impl AsyncRead + Unpin)) -> Result<i32> {
+    let mut value = 0;
+
+    for i in 0..5 {
+        let byte = stream.read_u8().await?;
+        value |= ((byte & 0x7F) as i32) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    bail!("VarInt is too big")
+}
+
+fn write_varint(buffer: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+
+    loop {
+        if value & !0x7F == 0 {
+            buffer.push(value as u8);
+            return;
+        }
+
+        buffer.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+/// Concatenates the text of a chat component and its children.
+pub(crate) fn plain_text(component: &Value) -> String {
+    match component {
+        Value::String(text) => text.clone(),
+        Value::Array(components) => components.iter().map(plain_text).collect(),
+        Value::Object(fields) => {
+            let mut text = fields
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+
+            if let Some(Value::Array(extra)) = fields.get("extra") {
+                text.extend(extra.iter().map(plain_text));
+            }
+
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+/// Removes legacy `§` color and style codes, which many servers still put in their MOTD.
+fn strip_formatting(text: &str) -> String {
+    let mut chars = text.chars();
+    let mut stripped = String::with_capacity(text.len());
+
+    while let Some(c) = chars.next() {
+        match c {
+            '§' => {
+                chars.next();
+            }
+            c => stripped.push(c),
+        }
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn varint_round_trip() {
+        for value in [0, 1, 127, 128, 255, 25565, 2097151, i32::MAX, -1, i32::MIN] {
+            let mut buffer = Vec::new();
+            write_varint(&mut buffer, value);
+
+            assert!(buffer.len() <= 5);
+            assert_eq!(read_varint(&mut buffer.as_slice()).await.unwrap(), value);
+        }
+
+        let mut buffer = Vec::new();
+        write_varint(&mut buffer, 300);
+        assert_eq!(buffer, [0xAC, 0x02]);
+    }
+
+    #[tokio::test]
+    async fn varint_too_big() {
+        let bytes = [0xFF; 6];
+        assert!(read_varint(&mut &bytes[..]).await.is_err());
+    }
+
+    #[test]
+    fn plain_text_of_components() {
+        assert_eq!(plain_text(&json!("A Server")), "A Server");
+
+        let component = json!({
+            "text": "A ",
+            "color": "gold",
+            "extra": ["Mine", {"text": "craft", "extra": [{"text": " Server"}]}],
+        });
+        assert_eq!(plain_text(&component), "A Minecraft Server");
+
+        assert_eq!(plain_text(&json!([{"text": "A"}, "B"])), "AB");
+        assert_eq!(plain_text(&json!({"translate": "key"})), "");
+        assert_eq!(plain_text(&json!(null)), "");
+    }
+
+    #[test]
+    fn strips_formatting_codes() {
+        assert_eq!(strip_formatting("§6§lA §rServer"), "A Server");
+        assert_eq!(strip_formatting("No codes"), "No codes");
+        // A trailing `§` has no code to remove
+        assert_eq!(strip_formatting("Server§"), "Server");
+    }
+
+    #[tokio::test]
+    async fn pings_a_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let handshake = read_packet(&mut stream, 0x00).await.unwrap();
+            let request = read_packet(&mut stream, STATUS_REQUEST).await.unwrap();
+
+            let response = json!({
+                "version": {"name": "1.8.9", "protocol": 47},
+                "players": {
+                    "max": 20,
+                    "online": 1,
+                    "sample": [{"name": "Steve", "id": "8667ba71-b85a-4004-af54-457a9734eed7"}],
+                },
+                "description": {"text": "§aA ", "extra": ["Server"]},
+            })
+            .to_string();
+
+            let mut body = Vec::new();
+            write_varint(&mut body, response.len() as i32);
+            body.extend_from_slice(response.as_bytes());
+            write_packet(&mut stream, STATUS_REQUEST, &body)
+                .await
+                .unwrap();
+
+            let payload = read_packet(&mut stream, PING).await.unwrap();
+            write_packet(&mut stream, PING, &payload).await.unwrap();
+
+            (handshake, request)
+        });
+
+        let status = ping("127.0.0.1", port).await.unwrap();
+        let (handshake, request) = server.await.unwrap();
+
+        // Protocol version, then the next state, status, at the end
+        assert_eq!(handshake.first(), Some(&(PROTOCOL_VERSION as u8)));
+        assert_eq!(handshake.last(), Some(&1));
+        assert!(request.is_empty());
+
+        assert_eq!(status.version.name, "1.8.9");
+        assert_eq!(status.version.protocol, 47);
+        assert_eq!((status.players.online, status.players.max), (1, 20));
+        assert_eq!(status.players.sample[0].name, "Steve");
+        assert_eq!(status.motd(), "A Server");
+        assert_eq!(status.favicon, None);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_wrong_pong() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            read_packet(&mut stream, 0x00).await.unwrap();
+            read_packet(&mut stream, STATUS_REQUEST).await.unwrap();
+
+            let response = json!({"version": {"name": "1.8.9", "protocol": 47}}).to_string();
+            let mut body = Vec::new();
+            write_varint(&mut body, response.len() as i32);
+            body.extend_from_slice(response.as_bytes());
+            write_packet(&mut stream, STATUS_REQUEST, &body)
+                .await
+                .unwrap();
+
+            read_packet(&mut stream, PING).await.unwrap();
+            write_packet(&mut stream, PING, &0i64.to_be_bytes())
+                .await
+                .unwrap();
+        });
+
+        assert!(ping("127.0.0.1", port).await.is_err());
+    }
+}
@@ -1,8 +1,11 @@
+use std::any::{type_name, Any};
+use std::cell::RefCell;
 use std::io::Cursor;
+use std::rc::Rc;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bytes::{Bytes, BytesMut};
-use gami_mc_protocol::packets::login::server::{LoginSuccess, SetCompression};
+use gami_mc_protocol::packets::login::server::{EncryptionBegin, LoginSuccess, SetCompression};
 use gami_mc_protocol::packets::play::server::KeepAlive;
 use gami_mc_protocol::packets::{Packet, Packets};
 use gami_mc_protocol::registry::tcp::{Origin, State};
@@ -13,20 +16,78 @@ use tokio::net::TcpStream;
 use tokio::spawn;
 use tokio::sync::mpsc;
 
+use crate::crypto::{self, Decryptor, Encryptor, SharedSecret};
+
+/// 1.8.x, the only protocol `gami_mc_protocol` implements packets for.
+pub(crate) const PROTOCOL_VERSION: i32 = 47;
 const ORIGIN: Origin = Origin::Server;
 
+pub(crate) type OutgoingHook = Box<dyn FnMut(&mut OutgoingPacket)>;
+pub(crate) type OutgoingHooks = Rc<RefCell<Vec<OutgoingHook>>>;
+
+/// A client packet about to be sent, hooks can inspect, modify or cancel it.
+///
+/// Packets the connection depends on, keep alive answers, the login encryption response and
+/// teleport confirmations, are protected: hooks still see them, but they are always sent
+/// unchanged. Dropping or altering them would get the bot kicked.
+pub struct OutgoingPacket<'a> {
+    packet: &'a mut dyn Any,
+    name: &'static str,
+    canceled: bool,
+    protected: bool,
+}
+
+impl OutgoingPacket<'_> {
+    /// The packet's type name, e.g. `gami_mc_protocol::packets::play::client::Chat`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is<T: 'static>(&self) -> bool {
+        self.packet.is::<T>()
+    }
+
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.packet.downcast_ref()
+    }
+
+    pub fn downcast_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.packet.downcast_mut()
+    }
+
+    /// Drops the packet, later hooks don't see it. Ignored for protected packets.
+    pub fn cancel(&mut self) {
+        self.canceled = true;
+    }
+
+    /// Whether the packet is sent unchanged whatever the hooks do, see `OutgoingPacket`.
+    pub fn is_protected(&self) -> bool {
+        self.protected
+    }
+}
+
+enum Frame {
+    Data(Bytes),
+    /// Everything written after this frame is encrypted.
+    Encrypt(Encryptor),
+    /// Closes the connection once the frames before it are written.
+    Shutdown,
+}
+
 pub struct Stream {
     pub reader: OwnedReadHalf,
     pub compression_threshold: i32,
     pub state: State,
-    tx: mpsc::Sender<Bytes>,
+    tx: mpsc::Sender<Frame>,
     buffer: BytesMut,
+    decryptor: Option<Decryptor>,
+    hooks: OutgoingHooks,
 }
 
 impl Stream {
-    pub fn new(stream: TcpStream) -> Self {
+    pub fn new(stream: TcpStream, hooks: OutgoingHooks) -> Self {
         let (reader, writer) = stream.into_split();
-        let (tx, rx) = mpsc::channel::<Bytes>(500);
+        let (tx, rx) = mpsc::channel::<Frame>(500);
 
         spawn(Self::writer_task(writer, rx));
 
@@ -36,26 +97,82 @@ impl Stream {
             compression_threshold: -1,
             state: State::Login,
             buffer: BytesMut::with_capacity(500_000),
+            decryptor: None,
+            hooks,
         }
     }
 
-    async fn writer_task(mut writer: OwnedWriteHalf, mut rx: mpsc::Receiver<Bytes>) -> Result<()> {
-        while let Some(data) = rx.recv().await {
-            writer.write_all(&data).await?;
+    async fn writer_task(mut writer: OwnedWriteHalf, mut rx: mpsc::Receiver<Frame>) -> Result<()> {
+        let mut encryptor = None;
+
+        while let Some(frame) = rx.recv().await {
+            let data = match frame {
+                Frame::Data(data) => data,
+                Frame::Encrypt(cipher) => {
+                    encryptor = Some(cipher);
+                    continue;
+                }
+                Frame::Shutdown => break,
+            };
+
+            match encryptor {
+                Some(ref mut cipher) => {
+                    let mut data = data.to_vec();
+                    crypto::encrypt(cipher, &mut data);
+                    writer.write_all(&data).await?;
+                }
+                None => writer.write_all(&data).await?,
+            }
+
             writer.flush().await?;
         }
         writer.shutdown().await?;
         Ok(())
     }
 
+    /// Encrypts both directions from now on, must be called right after sending the
+    /// encryption response.
+    pub async fn enable_encryption(&mut self, secret: &SharedSecret) -> Result<()> {
+        let (encryptor, decryptor) = crypto::ciphers(secret);
+        self.decryptor = Some(decryptor);
+        self.tx.send(Frame::Encrypt(encryptor)).await?;
+
+        Ok(())
+    }
+
+    /// Closes the write side of the connection, the server then ends the session.
+    pub async fn shutdown(&self) {
+        // Fails if the writer task already stopped, in which case the socket is closed
+        let _ = self.tx.send(Frame::Shutdown).await;
+    }
+
     pub async fn read_packets(&mut self) -> Result<Vec<Packets>> {
+        self.read().await?;
+        self.decode_packets().await
+    }
+
+    /// Waits for more bytes from the server. Cancel safe, so it can race the bot's tick
+    /// interval in a `select!`.
+    pub async fn read(&mut self) -> Result<()> {
+        let start = self.buffer.len();
         let n = self.reader.read_buf(&mut self.buffer).await?;
-        let mut packets = Vec::new();
 
         if n == 0 {
-            return Ok(packets);
+            bail!("Connection closed by the server");
+        }
+
+        // Nothing is awaited past the read, so a cancel can't leave bytes undecrypted
+        if let Some(ref mut cipher) = self.decryptor {
+            crypto::decrypt(cipher, &mut self.buffer[start..]);
         }
 
+        Ok(())
+    }
+
+    /// Takes every complete packet out of the bytes read so far.
+    pub async fn decode_packets(&mut self) -> Result<Vec<Packets>> {
+        let mut packets = Vec::new();
+
         while let Ok((length, vbytes)) = decode_varint_length(&self.buffer) {
             if self.buffer.len() < vbytes + length {
                 break;
@@ -108,6 +225,12 @@ impl Stream {
             return Ok(Some(Packets::SetCompression(packet)));
         }
 
+        // Handle Encryption Request
+        if id == 0x01 && self.state == State::Login {
+            let packet = EncryptionBegin::deserialize(bytes)?;
+            return Ok(Some(Packets::EncryptionBegin(packet)));
+        }
+
         // Handle Login Success
         if id == 0x02 && self.state == State::Login {
             self.state = State::Play;
@@ -118,26 +241,82 @@ impl Stream {
         // Handle Keep Alive
         if id == 0x00 && self.state == State::Play {
             let packet = KeepAlive::deserialize(bytes)?;
-            self.send_packet(&packet).await?;
+            self.send_protected_packet(&packet).await?;
             return Ok(Some(Packets::ServerKeepAlive(packet)));
         }
 
         Ok(None)
     }
 
-    pub async fn send_packet(&self, packet: &impl Packet) -> Result<()> {
-        let bytes = Packet::serialize(packet, self.compression_threshold)?;
-        self.tx.send(bytes.into()).await?;
+    /// Runs the outgoing hooks and serializes the packet, `None` if a hook canceled it.
+    /// Protected packets are serialized as given, the hooks only see a copy.
+    fn prepare<P: Packet + Clone + 'static>(
+        &self,
+        packet: &P,
+        protected: bool,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut hooks = self.hooks.borrow_mut();
+
+        // Only pay for the clone when something can modify the packet
+        if hooks.is_empty() {
+            return Ok(Some(Packet::serialize(packet, self.compression_threshold)?));
+        }
+
+        let mut copy = packet.clone();
+        let mut outgoing = OutgoingPacket {
+            packet: &mut copy,
+            name: type_name::<P>(),
+            canceled: false,
+            protected,
+        };
+
+        for hook in hooks.iter_mut() {
+            hook(&mut outgoing);
+
+            if outgoing.canceled && !protected {
+                return Ok(None);
+            }
+        }
+
+        let packet = match protected {
+            true => packet,
+            false => &copy,
+        };
+
+        Ok(Some(Packet::serialize(packet, self.compression_threshold)?))
+    }
+
+    pub async fn send_packet<P: Packet + Clone + 'static>(&self, packet: &P) -> Result<()> {
+        self.send(packet, false).await
+    }
+
+    /// Sends a packet the connection depends on, which hooks can't cancel or modify.
+    pub(crate) async fn send_protected_packet<P: Packet + Clone + 'static>(
+        &self,
+        packet: &P,
+    ) -> Result<()> {
+        self.send(packet, true).await
+    }
+
+    async fn send<P: Packet + Clone + 'static>(&self, packet: &P, protected: bool) -> Result<()> {
+        let Some(bytes) = self.prepare(packet, protected)? else {
+            return Ok(());
+        };
+
+        self.tx.send(Frame::Data(bytes.into())).await?;
 
         Ok(())
     }
 
-    pub fn send_packet_sync(&self, packet: &impl Packet) -> Result<()> {
-        let bytes = Packet::serialize(packet, self.compression_threshold)?;
+    pub fn send_packet_sync<P: Packet + Clone + 'static>(&self, packet: &P) -> Result<()> {
+        let Some(bytes) = self.prepare(packet, false)? else {
+            return Ok(());
+        };
+
         let tx = self.tx.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = tx.send(bytes.into()).await {
+            if let Err(e) = tx.send(Frame::Data(bytes.into())).await {
                 eprintln!("Error sending packet: {:?}", e);
             }
         });
@@ -145,3 +324,57 @@ impl Stream {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use gami_mc_protocol::packets::play::client::PositionLook;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn hooks_cannot_cancel_protected_packets() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+
+        let hooks = OutgoingHooks::default();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let log = Rc::clone(&seen);
+
+        hooks
+            .borrow_mut()
+            .push(Box::new(move |outgoing: &mut OutgoingPacket| {
+                log.borrow_mut().push(outgoing.is_protected());
+
+                if let Some(position) = outgoing.downcast_mut::<PositionLook>() {
+                    position.y = 0.0;
+                }
+
+                outgoing.cancel();
+            }));
+
+        let packet = PositionLook {
+            x: 0.5,
+            y: 64.0,
+            z: 0.5,
+            yaw: 0.0,
+            pitch: 0.0,
+            on_ground: false,
+        };
+
+        let stream = Stream::new(client, hooks);
+        stream.send_packet(&packet).await.unwrap();
+        stream.send_protected_packet(&packet).await.unwrap();
+        // Closes the writer once the queued frames are written
+        drop(stream);
+
+        let mut received = vec![];
+        server.read_to_end(&mut received).await.unwrap();
+
+        assert_eq!(*seen.borrow(), [false, true]);
+        assert_eq!(received, Packet::serialize(&packet, -1).unwrap());
+    }
+}
@@ -0,0 +1,171 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::oneshot;
+
+/// Returns `true` once the waiter is done and can be dropped.
+type Waiter = Box<dyn FnMut(&dyn Any) -> bool>;
+
+/// Pending `wait_for` futures, keyed by the payload type they wait for.
+#[derive(Default)]
+pub(crate) struct Waiters {
+    waiters: HashMap<TypeId, Vec<(u64, Waiter)>>,
+    next_id: u64,
+}
+
+pub(crate) type SharedWaiters = Rc<RefCell<Waiters>>;
+
+/// Held by the `wait_for` future, removes its waiter once it times out or is dropped.
+struct Registration {
+    waiters: Weak<RefCell<Waiters>>,
+    kind: TypeId,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let Some(waiters) = self.waiters.upgrade() else {
+            return;
+        };
+
+        // Already borrowed means it's resolving, which drops closed waiters itself
+        if let Ok(mut waiters) = waiters.try_borrow_mut() {
+            waiters.remove(self.kind, |id| id == self.id);
+        }
+    }
+}
+
+impl Waiters {
+    /// Registers right away, so a payload received before the future is first polled
+    /// still resolves it.
+    pub fn register<T: Clone + 'static>(
+        waiters: &SharedWaiters,
+        predicate: impl Fn(&T) -> bool + 'static,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<T>> {
+        let (tx, rx) = oneshot::channel();
+        let mut tx = Some(tx);
+
+        let waiter: Waiter = Box::new(move |payload| {
+            let Some(payload) = payload.downcast_ref::<T>() else {
+                return false;
+            };
+
+            // The future timed out or was dropped
+            if tx.as_ref().map_or(true, |tx| tx.is_closed()) {
+                return true;
+            }
+
+            if !predicate(payload) {
+                return false;
+            }
+
+            if let Some(tx) = tx.take() {
+                let _ = tx.send(payload.clone());
+            }
+
+            true
+        });
+
+        let kind = TypeId::of::<T>();
+
+        let id = {
+            let mut waiters = waiters.borrow_mut();
+            let id = waiters.next_id;
+            waiters.next_id += 1;
+            waiters.waiters.entry(kind).or_default().push((id, waiter));
+            id
+        };
+
+        let registration = Registration {
+            waiters: Rc::downgrade(waiters),
+            kind,
+            id,
+        };
+
+        async move {
+            let _registration = registration;
+
+            match tokio::time::timeout(timeout, rx).await {
+                Ok(Ok(payload)) => Ok(payload),
+                Ok(Err(_)) => Err(anyhow!("Bot disconnected while waiting")),
+                Err(_) => Err(anyhow!(
+                    "Timed out waiting for {}",
+                    std::any::type_name::<T>()
+                )),
+            }
+        }
+    }
+
+    pub fn resolve<T: 'static>(&mut self, payload: &T) {
+        if let Some(waiters) = self.waiters.get_mut(&TypeId::of::<T>()) {
+            waiters.retain_mut(|(_, waiter)| !waiter(payload));
+        }
+
+        self.remove(TypeId::of::<T>(), |_| false);
+    }
+
+    /// Removes the matching waiters, and the type's entry once none are left.
+    fn remove(&mut self, kind: TypeId, matches: impl Fn(u64) -> bool) {
+        let Some(waiters) = self.waiters.get_mut(&kind) else {
+            return;
+        };
+
+        waiters.retain(|(id, _)| !matches(*id));
+
+        if waiters.is_empty() {
+            self.waiters.remove(&kind);
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.waiters.values().map(Vec::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn resolves_matching_payloads() {
+        let waiters = SharedWaiters::default();
+        let even = Waiters::register(&waiters, |n: &i32| n % 2 == 0, Duration::from_secs(5));
+
+        waiters.borrow_mut().resolve(&1);
+        waiters.borrow_mut().resolve(&2);
+
+        assert_eq!(even.await.unwrap(), 2);
+        assert_eq!(waiters.borrow().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn removes_timed_out_waiters() {
+        let waiters = SharedWaiters::default();
+        let wait = Waiters::register(&waiters, |_: &i32| true, Duration::from_millis(10));
+
+        assert_eq!(waiters.borrow().len(), 1);
+        assert!(wait.await.is_err());
+        assert_eq!(waiters.borrow().len(), 0);
+        assert!(waiters.borrow().waiters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn removes_dropped_waiters() {
+        let waiters = SharedWaiters::default();
+        let wait = Waiters::register(&waiters, |_: &String| true, Duration::from_secs(5));
+        let other = Waiters::register(&waiters, |_: &String| true, Duration::from_secs(5));
+
+        drop(wait);
+        assert_eq!(waiters.borrow().len(), 1);
+
+        waiters.borrow_mut().resolve(&"hi".to_string());
+        assert_eq!(other.await.unwrap(), "hi");
+    }
+}
@@ -1,9 +1,624 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 
+use anyhow::{bail, Result};
+use gami_mc_protocol::packets::play::server::{
+    BlockChange, EntityDestroy, EntityHeadRotation, EntityLook, EntityMoveLook, EntityRelativeMove,
+    EntityTeleport, EntityVelocity, Explosion, MapChunk, MapChunkBulk, MultiBlockChange,
+};
 use gami_mc_protocol::registry::{Dimension, EntityKind};
 
+use crate::blocks;
+use crate::cache::WorldCache;
+use crate::chunk::{BlockPos, BlockState, Chunk};
+use crate::entity::{from_angle, Transform};
+use crate::math::{Aabb, Vec3};
+use crate::shared::{ChunkEntry, ChunkMap, SharedChunks};
+use crate::spatial::SpatialIndex;
+
+/// Entity positions are at their feet, aim a bit higher when checking visibility.
+const SIGHT_OFFSET: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockUpdate {
+    pub position: BlockPos,
+    /// The state before the packet was applied. Equal to `new` when the server resent the
+    /// block, or when another bot sharing the chunk already applied the same packet.
+    pub old: BlockState,
+    pub new: BlockState,
+}
+
 #[derive(Default)]
 pub struct World {
     pub dimension: Dimension,
     pub entities: HashMap<i32, EntityKind>,
+    transforms: HashMap<i32, Transform>,
+    index: SpatialIndex,
+    chunks: SharedChunks,
+    /// Chunks the server sent to this world and hasn't unloaded yet.
+    view: HashSet<(i32, i32)>,
+    cache: Option<WorldCache>,
+    /// Chunks read from the cache around `cache_center`, kept apart from the server's view.
+    cached: HashSet<(i32, i32)>,
+    cache_center: Option<(i32, i32)>,
+}
+
+impl World {
+    /// Moves this world's chunks to another store, usually one handed out by `SharedWorlds`.
+    pub fn attach(&mut self, chunks: SharedChunks) {
+        self.release_view();
+        self.release_cached();
+        self.chunks = chunks;
+    }
+
+    /// Sets the disk cache, it should already be scoped to the server and dimension.
+    pub fn set_cache(&mut self, cache: Option<WorldCache>) {
+        self.release_cached();
+        self.cache = cache;
+    }
+
+    pub fn shared_chunks(&self) -> &SharedChunks {
+        &self.chunks
+    }
+
+    /// Whether the chunk is known, it might have been received by another bot sharing the store.
+    pub fn is_chunk_loaded(&self, cx: i32, cz: i32) -> bool {
+        self.chunks.contains(cx, cz)
+    }
+
+    pub fn with_chunk<R>(&self, cx: i32, cz: i32, f: impl FnOnce(&Chunk) -> R) -> Option<R> {
+        self.chunks
+            .read()
+            .get(&(cx, cz))
+            .map(|entry| f(&entry.chunk))
+    }
+
+    pub fn with_chunk_mut<R>(
+        &mut self,
+        cx: i32,
+        cz: i32,
+        f: impl FnOnce(&mut Chunk) -> R,
+    ) -> Option<R> {
+        self.chunks.write().get_mut(&(cx, cz)).map(|entry| {
+            entry.dirty = true;
+            f(&mut entry.chunk)
+        })
+    }
+
+    /// Positions of the chunks inside this world's own view.
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = &(i32, i32)> {
+        self.view.iter()
+    }
+
+    /// Returns `None` when the chunk containing the block isn't loaded.
+    pub fn block_at(&self, x: i32, y: i32, z: i32) -> Option<BlockState> {
+        let chunks = self.chunks.read();
+        let chunk = &chunks.get(&(x >> 4, z >> 4))?.chunk;
+
+        if !(0..256).contains(&y) {
+            return Some(BlockState::AIR);
+        }
+
+        Some(chunk.block((x & 0x0F) as usize, y as usize, (z & 0x0F) as usize))
+    }
+
+    /// Returns the replaced block state, or `None` if the block isn't in a loaded chunk.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, state: BlockState) -> Option<BlockState> {
+        if !(0..256).contains(&y) {
+            return None;
+        }
+
+        self.with_chunk_mut(x >> 4, z >> 4, |chunk| {
+            chunk.set_block((x & 0x0F) as usize, y as usize, (z & 0x0F) as usize, state)
+        })
+    }
+
+    fn update_block(&mut self, position: BlockPos, new: BlockState) -> Option<BlockUpdate> {
+        let old = self.set_block(position.x, position.y, position.z, new)?;
+        Some(BlockUpdate { position, old, new })
+    }
+
+    pub fn apply_block_change(&mut self, data: &BlockChange) -> Option<BlockUpdate> {
+        let position = BlockPos::new(data.location.x, data.location.y, data.location.z);
+        self.update_block(position, BlockState::from_raw(data.block_id as u16))
+    }
+
+    pub fn apply_multi_block_change(&mut self, data: &MultiBlockChange) -> Vec<BlockUpdate> {
+        data.records
+            .iter()
+            .filter_map(|record| {
+                let position = BlockPos::new(
+                    data.chunk_x * 16 + (record.horizontal_pos >> 4) as i32,
+                    record.y as i32,
+                    data.chunk_z * 16 + (record.horizontal_pos & 0x0F) as i32,
+                );
+
+                self.update_block(position, BlockState::from_raw(record.block_id as u16))
+            })
+            .collect()
+    }
+
+    pub fn apply_explosion(&mut self, data: &Explosion) -> Vec<BlockUpdate> {
+        let origin = BlockPos::new(data.x as i32, data.y as i32, data.z as i32);
+
+        data.affected_block_offsets
+            .iter()
+            .filter_map(|offset| {
+                let position = origin.offset(offset.x as i32, offset.y as i32, offset.z as i32);
+                self.update_block(position, BlockState::AIR)
+            })
+            .collect()
+    }
+
+    pub fn has_sky_light(&self) -> bool {
+        self.dimension == Dimension::Overworld
+    }
+
+    pub fn load_chunk(&mut self, data: &MapChunk) -> Result<()> {
+        // An empty ground-up chunk is how the server tells us to unload it
+        if data.ground_up && data.bit_map == 0 {
+            self.unload_chunk(data.x, data.z);
+            return Ok(());
+        }
+
+        let has_sky_light = self.has_sky_light();
+        let mut chunks = self.chunks.write();
+
+        // Decoded into a copy so a malformed packet leaves the stored chunk untouched
+        let mut chunk = match chunks.get(&(data.x, data.z)) {
+            Some(entry) if !data.ground_up => entry.chunk.clone(),
+            _ => Chunk::new(data.x, data.z, has_sky_light),
+        };
+
+        chunk.load(data.bit_map, data.ground_up, &data.chunk_data)?;
+
+        let entry = match chunks.entry((data.x, data.z)) {
+            Entry::Occupied(entry) => {
+                let entry = entry.into_mut();
+                entry.chunk = chunk;
+                entry.dirty = true;
+                entry
+            }
+            Entry::Vacant(entry) => entry.insert(ChunkEntry::new(chunk)),
+        };
+
+        if self.view.insert((data.x, data.z)) {
+            entry.viewers += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Columns that fail to decode are skipped, the others are still loaded before the error
+    /// is returned.
+    pub fn load_chunk_bulk(&mut self, data: &MapChunkBulk) -> Result<()> {
+        let mut chunks = self.chunks.write();
+        let mut offset = 0;
+        let mut failed = vec![];
+
+        for meta in &data.meta {
+            // Each column's length only depends on its bit map, so the next one is found even
+            // when this one fails to decode
+            let column = data.data.get(offset..).unwrap_or_default();
+            offset += Chunk::data_len(meta.bit_map, true, data.sky_light_sent);
+
+            let mut chunk = Chunk::new(meta.x, meta.z, data.sky_light_sent);
+
+            if let Err(e) = chunk.load(meta.bit_map, true, column) {
+                failed.push(e);
+                continue;
+            }
+
+            let entry = match chunks.entry((meta.x, meta.z)) {
+                Entry::Occupied(entry) => {
+                    let entry = entry.into_mut();
+                    entry.chunk = chunk;
+                    entry.dirty = true;
+                    entry
+                }
+                Entry::Vacant(entry) => entry.insert(ChunkEntry::new(chunk)),
+            };
+
+            if self.view.insert((meta.x, meta.z)) {
+                entry.viewers += 1;
+            }
+        }
+
+        match failed.first() {
+            Some(e) => bail!("Skipped {} chunks of a bulk: {e}", failed.len()),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops the chunk from this world's view, it is only evicted once no other world needs it.
+    pub fn unload_chunk(&mut self, cx: i32, cz: i32) {
+        if self.view.remove(&(cx, cz)) {
+            let evicted = Self::release(&mut self.chunks.write(), (cx, cz));
+            self.save_evicted(evicted.into_iter().collect());
+        }
+    }
+
+    /// Loads the cached chunks within the cache radius of the given chunk and releases the
+    /// ones that are now out of it. Does nothing if the center didn't change.
+    ///
+    /// Disk I/O runs on the blocking thread pool without holding the chunk store's lock, so
+    /// other bots sharing it aren't blocked.
+    pub async fn load_cached_around(&mut self, cx: i32, cz: i32) {
+        let Some(cache) = self.cache.clone() else {
+            return;
+        };
+
+        if self.cache_center == Some((cx, cz)) {
+            return;
+        }
+
+        self.cache_center = Some((cx, cz));
+
+        let radius = cache.radius();
+        let mut evicted = Vec::new();
+        let mut missing = Vec::new();
+
+        {
+            let mut chunks = self.chunks.write();
+
+            let out_of_range: Vec<_> = self
+                .cached
+                .iter()
+                .filter(|(x, z)| (x - cx).abs() > radius || (z - cz).abs() > radius)
+                .copied()
+                .collect();
+
+            for pos in out_of_range {
+                self.cached.remove(&pos);
+                evicted.extend(Self::release(&mut chunks, pos));
+            }
+
+            for x in cx - radius..=cx + radius {
+                for z in cz - radius..=cz + radius {
+                    if self.cached.contains(&(x, z)) {
+                        continue;
+                    }
+
+                    match chunks.get_mut(&(x, z)) {
+                        Some(entry) => {
+                            entry.viewers += 1;
+                            self.cached.insert((x, z));
+                        }
+                        None => missing.push((x, z)),
+                    }
+                }
+            }
+        }
+
+        cache.save_in_background(evicted);
+
+        if missing.is_empty() {
+            return;
+        }
+
+        let loaded = cache.load_many(missing).await;
+        let mut chunks = self.chunks.write();
+
+        for chunk in loaded {
+            let pos = (chunk.x, chunk.z);
+
+            // Another world sharing the store may have received it in the meantime
+            let entry = match chunks.entry(pos) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let entry = entry.insert(ChunkEntry::new(chunk));
+                    entry.dirty = false;
+                    entry
+                }
+            };
+
+            entry.viewers += 1;
+            self.cached.insert(pos);
+        }
+    }
+
+    /// Writes every changed chunk to the disk cache.
+    pub async fn save_cache(&self) -> Result<()> {
+        let Some(ref cache) = self.cache else {
+            return Ok(());
+        };
+
+        let changed: Vec<_> = self
+            .chunks
+            .write()
+            .values_mut()
+            .filter(|entry| entry.dirty)
+            .map(|entry| {
+                entry.dirty = false;
+                entry.chunk.clone()
+            })
+            .collect();
+
+        cache.save_many(changed).await
+    }
+
+    /// Returns the chunk if no world needs it anymore and it has to be written to the cache.
+    fn release(chunks: &mut ChunkMap, pos: (i32, i32)) -> Option<Chunk> {
+        let entry = chunks.get_mut(&pos)?;
+        entry.viewers = entry.viewers.saturating_sub(1);
+
+        if entry.viewers > 0 {
+            return None;
+        }
+
+        let entry = chunks.remove(&pos)?;
+        entry.dirty.then_some(entry.chunk)
+    }
+
+    fn save_evicted(&self, evicted: Vec<Chunk>) {
+        if let Some(ref cache) = self.cache {
+            cache.save_in_background(evicted);
+        }
+    }
+
+    fn release_view(&mut self) {
+        let evicted: Vec<_> = {
+            let mut chunks = self.chunks.write();
+            self.view
+                .drain()
+                .filter_map(|pos| Self::release(&mut chunks, pos))
+                .collect()
+        };
+
+        self.save_evicted(evicted);
+    }
+
+    fn release_cached(&mut self) {
+        let evicted: Vec<_> = {
+            let mut chunks = self.chunks.write();
+            self.cached
+                .drain()
+                .filter_map(|pos| Self::release(&mut chunks, pos))
+                .collect()
+        };
+
+        self.cache_center = None;
+        self.save_evicted(evicted);
+    }
+
+    pub fn spawn_entity(&mut self, entity: EntityKind, transform: Transform) {
+        self.index.insert(entity.id(), transform.position);
+        self.transforms.insert(entity.id(), transform);
+        self.entities.insert(entity.id(), entity);
+    }
+
+    pub fn remove_entity(&mut self, id: i32) -> Option<EntityKind> {
+        self.index.remove(id);
+        self.transforms.remove(&id);
+        self.entities.remove(&id)
+    }
+
+    pub fn transform(&self, id: i32) -> Option<&Transform> {
+        self.transforms.get(&id)
+    }
+
+    fn update_transform(&mut self, id: i32, update: impl FnOnce(&mut Transform)) {
+        if let Some(transform) = self.transforms.get_mut(&id) {
+            update(transform);
+            self.index.insert(id, transform.position);
+        }
+    }
+
+    pub fn apply_relative_move(&mut self, data: &EntityRelativeMove) {
+        self.update_transform(data.entity_id, |transform| {
+            transform.translate(data.d_x, data.d_y, data.d_z);
+            transform.on_ground = data.on_ground;
+        });
+    }
+
+    pub fn apply_look(&mut self, data: &EntityLook) {
+        self.update_transform(data.entity_id, |transform| {
+            transform.rotate(data.yaw, data.pitch);
+            transform.on_ground = data.on_ground;
+        });
+    }
+
+    pub fn apply_move_look(&mut self, data: &EntityMoveLook) {
+        self.update_transform(data.entity_id, |transform| {
+            transform.translate(data.d_x, data.d_y, data.d_z);
+            transform.rotate(data.yaw, data.pitch);
+            transform.on_ground = data.on_ground;
+        });
+    }
+
+    pub fn apply_teleport(&mut self, data: &EntityTeleport) {
+        self.update_transform(data.entity_id, |transform| {
+            transform.teleport(data.x, data.y, data.z);
+            transform.rotate(data.yaw, data.pitch);
+            transform.on_ground = data.on_ground;
+        });
+    }
+
+    pub fn apply_head_rotation(&mut self, data: &EntityHeadRotation) {
+        self.update_transform(data.entity_id, |transform| {
+            transform.head_yaw = from_angle(data.head_yaw);
+        });
+    }
+
+    pub fn apply_velocity(&mut self, data: &EntityVelocity) {
+        self.update_transform(data.entity_id, |transform| {
+            transform.set_velocity(data.velocity_x, data.velocity_y, data.velocity_z);
+        });
+    }
+
+    pub fn apply_destroy(&mut self, data: &EntityDestroy) -> Vec<EntityKind> {
+        data.entity_ids
+            .iter()
+            .filter_map(|id| self.remove_entity(*id))
+            .collect()
+    }
+
+    pub fn entities_in(&self, area: Aabb) -> impl Iterator<Item = (&EntityKind, &Transform)> {
+        self.index.candidates(&area).filter_map(move |id| {
+            let transform = self.transforms.get(&id)?;
+
+            match area.contains(transform.position) {
+                true => Some((self.entities.get(&id)?, transform)),
+                false => None,
+            }
+        })
+    }
+
+    pub fn entities_within(
+        &self,
+        center: Vec3,
+        radius: f64,
+    ) -> impl Iterator<Item = (&EntityKind, &Transform)> {
+        self.entities_in(Aabb::around(center, radius))
+            .filter(move |(_, transform)| transform.position.distance(center) <= radius)
+    }
+
+    /// Finds the closest entity within `max_distance` matching the filter, e.g.
+    /// `|entity| matches!(entity, EntityKind::Pig(_))`.
+    pub fn nearest_entity(
+        &self,
+        from: Vec3,
+        max_distance: f64,
+        filter: impl Fn(&EntityKind) -> bool,
+    ) -> Option<(&EntityKind, &Transform)> {
+        self.entities_within(from, max_distance)
+            .filter(|(entity, _)| filter(entity))
+            .min_by(|(_, a), (_, b)| {
+                let a = a.position.distance_squared(from);
+                let b = b.position.distance_squared(from);
+                a.total_cmp(&b)
+            })
+    }
+
+    /// Returns every entity within `radius` of `eye` with no block in between.
+    pub fn entities_in_line_of_sight(
+        &self,
+        eye: Vec3,
+        radius: f64,
+    ) -> impl Iterator<Item = (&EntityKind, &Transform)> {
+        self.entities_within(eye, radius)
+            .filter(move |(_, transform)| {
+                let target = transform.position + Vec3::new(0.0, SIGHT_OFFSET, 0.0);
+                self.has_line_of_sight(eye, target)
+            })
+    }
+
+    /// Walks every block crossed by the segment and fails on the first occluding one.
+    /// Unloaded chunks are treated as occluding.
+    pub fn has_line_of_sight(&self, from: Vec3, to: Vec3) -> bool {
+        let direction = to - from;
+        let length = direction.length();
+
+        if length == 0.0 {
+            return true;
+        }
+
+        let direction = direction * (1.0 / length);
+        let mut block = (
+            from.x.floor() as i32,
+            from.y.floor() as i32,
+            from.z.floor() as i32,
+        );
+        let target = (
+            to.x.floor() as i32,
+            to.y.floor() as i32,
+            to.z.floor() as i32,
+        );
+
+        let axis = |origin: f64, direction: f64, block: i32| -> (i32, f64, f64) {
+            if direction > 0.0 {
+                (
+                    1,
+                    (block as f64 + 1.0 - origin) / direction,
+                    1.0 / direction,
+                )
+            } else if direction < 0.0 {
+                (-1, (origin - block as f64) / -direction, -1.0 / direction)
+            } else {
+                (0, f64::INFINITY, f64::INFINITY)
+            }
+        };
+
+        let (step_x, mut next_x, delta_x) = axis(from.x, direction.x, block.0);
+        let (step_y, mut next_y, delta_y) = axis(from.y, direction.y, block.1);
+        let (step_z, mut next_z, delta_z) = axis(from.z, direction.z, block.2);
+
+        while block != target {
+            if next_x < next_y && next_x < next_z {
+                if next_x > length {
+                    break;
+                }
+                block.0 += step_x;
+                next_x += delta_x;
+            } else if next_y < next_z {
+                if next_y > length {
+                    break;
+                }
+                block.1 += step_y;
+                next_y += delta_y;
+            } else {
+                if next_z > length {
+                    break;
+                }
+                block.2 += step_z;
+                next_z += delta_z;
+            }
+
+            match self.block_at(block.0, block.1, block.2) {
+                Some(state) if blocks::has_collision(state) => return false,
+                None => return false,
+                _ => {}
+            }
+        }
+
+        true
+    }
+
+    /// Forgets every chunk and entity, used when the bot changes dimension.
+    pub fn clear(&mut self) {
+        self.release_view();
+        self.release_cached();
+        self.entities.clear();
+        self.transforms.clear();
+        self.index.clear();
+    }
+}
+
+impl Drop for World {
+    fn drop(&mut self) {
+        self.release_view();
+        self.release_cached();
+    }
+}
+
+#[cfg(test)]
+impl World {
+    /// An overworld with empty chunks `radius` chunks around the origin, for tests that place
+    /// their own blocks.
+    pub(crate) fn empty(radius: i32) -> Self {
+        let world = Self::default();
+
+        {
+            let mut chunks = world.chunks.write();
+
+            for cx in -radius..=radius {
+                for cz in -radius..=radius {
+                    chunks.insert((cx, cz), ChunkEntry::new(Chunk::new(cx, cz, true)));
+                }
+            }
+        }
+
+        world
+    }
+
+    /// Fills the blocks between both corners, inclusive.
+    pub(crate) fn fill(&mut self, from: BlockPos, to: BlockPos, state: BlockState) {
+        for x in from.x.min(to.x)..=from.x.max(to.x) {
+            for y in from.y.min(to.y)..=from.y.max(to.y) {
+                for z in from.z.min(to.z)..=from.z.max(to.z) {
+                    self.set_block(x, y, z, state);
+                }
+            }
+        }
+    }
 }